@@ -0,0 +1,72 @@
+//! The playground datasets shared by the benchmarks. They are the same ones used by the
+//! `length_test_*` tests in `lib.rs`.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Primitives {
+    a: u8,
+    b: u16,
+    c: u32,
+    d: u64,
+    e: i8,
+    f: i16,
+    g: i32,
+    h: i64,
+    i: f32,
+    j: f64,
+    k: bool,
+    l: char,
+    m: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct PlaygroundData {
+    never: HashMap<String, Vec<u8>>,
+    gonna: Vec<u8>,
+    give: Option<i32>,
+    you: bool,
+    up: Option<Primitives>,
+}
+
+pub fn playground_data(entries: usize, entry_len: usize) -> PlaygroundData {
+    PlaygroundData {
+        never: (0..entries)
+            .map(|i| (i.to_string(), vec![i as u8; entry_len]))
+            .collect(),
+        gonna: (0..entries).map(|i| i as u8).collect(),
+        give: Some(1),
+        you: true,
+        up: Some(Primitives {
+            a: 1,
+            b: 2,
+            c: 3,
+            d: 4,
+            e: -1,
+            f: -2,
+            g: -3,
+            h: -4,
+            i: 1.0,
+            j: 2.0,
+            k: true,
+            l: 'a',
+            m: "hello".to_string(),
+        }),
+    }
+}
+
+pub fn datasets() -> Vec<(&'static str, PlaygroundData)> {
+    vec![
+        (
+            "small",
+            PlaygroundData {
+                you: false,
+                up: None,
+                ..playground_data(10, 10)
+            },
+        ),
+        ("medium", playground_data(100, 100)),
+        ("large", playground_data(1000, 100)),
+    ]
+}
@@ -0,0 +1,141 @@
+//! Serialization and deserialization speed and allocation counts of rust_fr compared to
+//! serde_json, rmp_serde and ciborium on the small, medium and large playground datasets.
+//!
+//! Run with `cargo bench --bench comparison`. The allocation counts are printed before the
+//! timings. rust_fr is measured with the default config and with the length-prefixed one.
+
+mod common;
+
+use common::{datasets, PlaygroundData};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use rust_fr::{config::Config, deserializer, serializer};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    hint::black_box,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// A global allocator that counts the allocations (and reallocations) and the allocated bytes.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Count the allocations and the allocated bytes of a single call to `f`.
+fn count_allocations<T>(f: impl FnOnce() -> T) -> (usize, usize) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let bytes = ALLOCATED_BYTES.load(Ordering::Relaxed);
+    let value = f();
+    let counts = (
+        ALLOCATIONS.load(Ordering::Relaxed) - allocations,
+        ALLOCATED_BYTES.load(Ordering::Relaxed) - bytes,
+    );
+    drop(value);
+    counts
+}
+
+const LENGTH_PREFIXED: Config = Config::new().length_prefixed(true);
+
+type Format = (
+    &'static str,
+    fn(&PlaygroundData) -> Vec<u8>,
+    fn(&[u8]) -> PlaygroundData,
+);
+
+fn formats() -> Vec<Format> {
+    vec![
+        (
+            "rust_fr",
+            |data| serializer::to_bytes(data).unwrap(),
+            |bytes| deserializer::from_bytes(bytes).unwrap(),
+        ),
+        (
+            "rust_fr_lp",
+            |data| serializer::to_bytes_with_config(data, LENGTH_PREFIXED).unwrap(),
+            |bytes| deserializer::from_bytes_with_config(bytes, LENGTH_PREFIXED).unwrap(),
+        ),
+        (
+            "serde_json",
+            |data| serde_json::to_vec(data).unwrap(),
+            |bytes| serde_json::from_slice(bytes).unwrap(),
+        ),
+        (
+            "rmp_serde",
+            |data| rmp_serde::to_vec(data).unwrap(),
+            |bytes| rmp_serde::from_slice(bytes).unwrap(),
+        ),
+        (
+            "ciborium",
+            |data| {
+                let mut bytes = Vec::new();
+                ciborium::ser::into_writer(data, &mut bytes).unwrap();
+                bytes
+            },
+            |bytes| ciborium::de::from_reader(bytes).unwrap(),
+        ),
+    ]
+}
+
+fn allocations() {
+    println!("---- Allocations per call (count / bytes) ----");
+    println!("dataset\tformat\t\tserialize\t\tdeserialize");
+    for (name, data) in datasets() {
+        for (format, serialize, deserialize) in formats() {
+            let bytes = serialize(&data);
+            let (ser_count, ser_bytes) = count_allocations(|| serialize(&data));
+            let (de_count, de_bytes) = count_allocations(|| deserialize(&bytes));
+            println!(
+                "{name}\t{format:<10}\t{ser_count:>6} / {ser_bytes:>8}\t{de_count:>6} / {de_bytes:>8}"
+            );
+        }
+    }
+}
+
+fn serialize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("comparison/serialize");
+    for (name, data) in datasets() {
+        for (format, serialize, _) in formats() {
+            group.bench_with_input(BenchmarkId::new(format, name), &data, |b, data| {
+                b.iter(|| serialize(black_box(data)))
+            });
+        }
+    }
+    group.finish();
+}
+
+fn deserialize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("comparison/deserialize");
+    for (name, data) in datasets() {
+        for (format, serialize, deserialize) in formats() {
+            let bytes = serialize(&data);
+            group.bench_with_input(BenchmarkId::new(format, name), &bytes, |b, bytes| {
+                b.iter(|| deserialize(black_box(bytes)))
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, serialize, deserialize);
+criterion_main!(allocations, benches);
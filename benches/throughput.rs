@@ -0,0 +1,71 @@
+//! Serialization and deserialization throughput of rust_fr on the small, medium and large
+//! playground datasets.
+//!
+//! Run with `cargo bench --bench throughput`.
+
+mod common;
+
+use common::{datasets, PlaygroundData};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rust_fr::{config::Config, deserializer, serializer};
+use std::hint::black_box;
+
+fn configs() -> Vec<(&'static str, Config)> {
+    vec![
+        ("default", Config::new()),
+        ("length_prefixed", Config::new().length_prefixed(true)),
+        (
+            "byte_aligned",
+            Config::new().length_prefixed(true).byte_aligned(true),
+        ),
+    ]
+}
+
+fn serialize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("serialize");
+    for (name, data) in datasets() {
+        for (config_name, config) in configs() {
+            let bytes = serializer::to_bytes_with_config(&data, config).unwrap();
+            group.throughput(Throughput::Bytes(bytes.len() as u64));
+            group.bench_with_input(BenchmarkId::new(config_name, name), &data, |b, data| {
+                b.iter(|| serializer::to_bytes_with_config(black_box(data), config).unwrap())
+            });
+        }
+    }
+    group.finish();
+}
+
+fn deserialize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("deserialize");
+    for (name, data) in datasets() {
+        for (config_name, config) in configs() {
+            let bytes = serializer::to_bytes_with_config(&data, config).unwrap();
+            group.throughput(Throughput::Bytes(bytes.len() as u64));
+            group.bench_with_input(BenchmarkId::new(config_name, name), &bytes, |b, bytes| {
+                b.iter(|| {
+                    deserializer::from_bytes_with_config::<PlaygroundData>(black_box(bytes), config)
+                        .unwrap()
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+fn strings(c: &mut Criterion) {
+    let mut group = c.benchmark_group("string");
+    // 1 MB string behind a bool so that it does not start at a byte boundary.
+    let data = (true, "a".repeat(1 << 20));
+    let bytes = serializer::to_bytes(&data).unwrap();
+    group.throughput(Throughput::Bytes(bytes.len() as u64));
+    group.bench_function("serialize/1mb", |b| {
+        b.iter(|| serializer::to_bytes(black_box(&data)).unwrap())
+    });
+    group.bench_function("deserialize/1mb", |b| {
+        b.iter(|| deserializer::from_bytes::<(bool, String)>(black_box(&bytes)).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, serialize, deserialize, strings);
+criterion_main!(benches);
@@ -0,0 +1,392 @@
+//! ### Bits
+//! Internal module with the bit level writer and reader used by the serializer and the
+//! deserializer. By default bits are packed least significant bit first, i.e. the first bit
+//! written is the lowest bit of the first byte; with [`BitOrder::Msb0`] it is the highest bit
+//! and values are written most significant bit first. Both work a 64-bit word at a time
+//! instead of bit by bit.
+
+// `usize::is_multiple_of` needs Rust 1.87.
+#![allow(unknown_lints, clippy::manual_is_multiple_of)]
+
+use super::{config::BitOrder, error::Error};
+use alloc::{borrow::Cow, vec::Vec};
+use core::hash::Hasher;
+
+/// The destination of the bytes written by a [`BitWriter`]. The trait is public so that it
+/// can bound the public serializer but it lives in a private module, i.e. it can't be
+/// implemented outside of the crate.
+pub trait Sink {
+    /// Write whole bytes to the sink.
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Error>;
+}
+
+impl Sink for Vec<u8> {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.extend_from_slice(bytes);
+        Ok(())
+    }
+}
+
+impl<S: Sink + ?Sized> Sink for &mut S {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        (**self).write(bytes)
+    }
+}
+
+/// A sink that writes to a caller-provided buffer and fails once the buffer is full.
+#[derive(Debug)]
+pub(crate) struct SliceSink<'a> {
+    buffer: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> SliceSink<'a> {
+    pub fn new(buffer: &'a mut [u8]) -> Self {
+        Self { buffer, len: 0 }
+    }
+
+    /// Get the bytes written so far.
+    pub fn written(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+}
+
+impl Sink for SliceSink<'_> {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let end = self.len + bytes.len();
+        self.buffer
+            .get_mut(self.len..end)
+            .ok_or(Error::BufferTooSmall)?
+            .copy_from_slice(bytes);
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// A sink that feeds the bytes to a hasher.
+#[derive(Debug)]
+pub(crate) struct HashSink<'a, H: Hasher>(pub &'a mut H);
+
+impl<H: Hasher> Sink for HashSink<'_, H> {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.0.write(bytes);
+        Ok(())
+    }
+}
+
+/// A sink that throws the bytes away. Used to count the written bits without storing them.
+#[derive(Debug)]
+pub(crate) struct Discard;
+
+impl Sink for Discard {
+    fn write(&mut self, _bytes: &[u8]) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Writes bits to a [`Sink`]. Bits are collected in a 64-bit accumulator which is flushed
+/// to the sink whenever it is full.
+#[derive(Debug)]
+pub(crate) struct BitWriter<S: Sink = Vec<u8>> {
+    sink: S,
+    flushed: usize,
+    acc: u64,
+    pending: usize,
+    order: BitOrder,
+}
+
+/// Get the bytes of a word of the accumulator, in the order they are written.
+fn word_to_bytes(word: u64, order: BitOrder) -> [u8; 8] {
+    match order {
+        BitOrder::Lsb0 => word.to_le_bytes(),
+        BitOrder::Msb0 => word.to_be_bytes(),
+    }
+}
+
+/// Get the word of the accumulator for bytes in the order they are written.
+fn word_from_bytes(bytes: [u8; 8], order: BitOrder) -> u64 {
+    match order {
+        BitOrder::Lsb0 => u64::from_le_bytes(bytes),
+        BitOrder::Msb0 => u64::from_be_bytes(bytes),
+    }
+}
+
+impl<S: Sink> BitWriter<S> {
+    pub fn new(sink: S, order: BitOrder) -> Self {
+        Self {
+            sink,
+            flushed: 0,
+            acc: 0,
+            pending: 0,
+            order,
+        }
+    }
+
+    /// Get the number of bits written so far.
+    pub fn len(&self) -> usize {
+        self.flushed * 8 + self.pending
+    }
+
+    /// Write the lowest `n` (at most 64) bits of `value`.
+    pub fn write_bits(&mut self, value: u64, n: usize) -> Result<(), Error> {
+        debug_assert!(n <= 64);
+        if n == 0 {
+            return Ok(());
+        }
+        let value = if n == 64 {
+            value
+        } else {
+            value & ((1 << n) - 1)
+        };
+        // the accumulator fills up from its lowest bit (Lsb0) or from its highest bit (Msb0).
+        let free = 64 - self.pending;
+        if n < free {
+            self.acc |= match self.order {
+                BitOrder::Lsb0 => value << self.pending,
+                BitOrder::Msb0 => value << (free - n),
+            };
+            self.pending += n;
+            return Ok(());
+        }
+        // the accumulator is full; flush it and keep the bits that did not fit.
+        let rest = n - free;
+        self.acc |= match self.order {
+            BitOrder::Lsb0 => value << self.pending,
+            BitOrder::Msb0 => value >> rest,
+        };
+        self.sink.write(&word_to_bytes(self.acc, self.order))?;
+        self.flushed += 8;
+        self.acc = match self.order {
+            _ if rest == 0 => 0,
+            BitOrder::Lsb0 => value >> free,
+            BitOrder::Msb0 => value << (64 - rest),
+        };
+        self.pending = rest;
+        Ok(())
+    }
+
+    /// Write a single bit.
+    pub fn write_bit(&mut self, bit: bool) -> Result<(), Error> {
+        self.write_bits(u64::from(bit), 1)
+    }
+
+    /// Write whole bytes. If the writer is at a byte boundary, the bytes are copied as is.
+    /// Otherwise they are written a word (8 bytes) at a time.
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        if self.pending % 8 == 0 {
+            self.flush()?;
+            self.flushed += bytes.len();
+            return self.sink.write(bytes);
+        }
+        let mut chunks = bytes.chunks_exact(8);
+        for chunk in &mut chunks {
+            let mut word = [0u8; 8];
+            word.copy_from_slice(chunk);
+            self.write_bits(word_from_bytes(word, self.order), 64)?;
+        }
+        for byte in chunks.remainder() {
+            self.write_bits(u64::from(*byte), 8)?;
+        }
+        Ok(())
+    }
+
+    /// Write the first `n` bits of the bytes, e.g. the output of another writer.
+    pub fn write_bit_slice(&mut self, bytes: &[u8], n: usize) -> Result<(), Error> {
+        self.write_bytes(&bytes[..n / 8])?;
+        if n % 8 != 0 {
+            let byte = u64::from(bytes[n / 8]);
+            match self.order {
+                BitOrder::Lsb0 => self.write_bits(byte, n % 8)?,
+                BitOrder::Msb0 => self.write_bits(byte >> (8 - n % 8), n % 8)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the sink. Bits that don't make up a whole byte yet are not in it; see [`Self::flush`].
+    /// Bytes written to it directly are not counted by [`Self::len`].
+    pub fn sink_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    /// Flush the pending bits to the sink, zero padding the last byte.
+    pub fn flush(&mut self) -> Result<(), Error> {
+        let len = self.pending.div_ceil(8);
+        self.sink
+            .write(&word_to_bytes(self.acc, self.order)[..len])?;
+        self.flushed += len;
+        self.acc = 0;
+        self.pending = 0;
+        Ok(())
+    }
+
+    /// Flush the pending bits and get the sink back. The last byte is zero padded.
+    pub fn finish(mut self) -> Result<S, Error> {
+        self.flush()?;
+        Ok(self.sink)
+    }
+}
+
+impl BitWriter<Vec<u8>> {
+    /// Throw away everything written so far but keep the capacity of the vector.
+    pub fn clear(&mut self) {
+        self.sink.clear();
+        self.flushed = 0;
+        self.acc = 0;
+        self.pending = 0;
+    }
+}
+
+/// Reads bits from borrowed or owned bytes. Values are read a 64-bit word at a time. Only
+/// borrowed bytes can be handed out with the `'de` lifetime.
+#[derive(Debug)]
+pub(crate) struct BitReader<'de> {
+    input: Cow<'de, [u8]>,
+    position: usize,
+    order: BitOrder,
+}
+
+impl<'de> BitReader<'de> {
+    pub fn new(input: impl Into<Cow<'de, [u8]>>, order: BitOrder) -> Self {
+        Self {
+            input: input.into(),
+            position: 0,
+            order,
+        }
+    }
+
+    /// Get the number of bits read so far.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Get the number of bits left.
+    pub fn remaining(&self) -> usize {
+        self.input.len() * 8 - self.position
+    }
+
+    /// Skip the next `n` bits.
+    pub fn advance(&mut self, n: usize) -> Result<(), Error> {
+        if n > self.remaining() {
+            return Err(Error::UnexpectedEOF);
+        }
+        self.position += n;
+        Ok(())
+    }
+
+    /// Get the next `n` (at most 64) bits as the lowest bits of an integer without removing them.
+    pub fn peek_bits(&self, n: usize) -> Result<u64, Error> {
+        if n > self.remaining() {
+            return Err(Error::NLargerThanLength(n, self.remaining()));
+        }
+        Ok(self.bits_at(self.position, n))
+    }
+
+    /// Copy the bits from `start` to `end` (already read) into `bytes`, zero padding the last byte.
+    pub fn copy_bits(&self, start: usize, end: usize, bytes: &mut Vec<u8>) {
+        bytes.clear();
+        for position in (start..end).step_by(8) {
+            let n = usize::min(end - position, 8);
+            let bits = self.bits_at(position, n);
+            bytes.push(match self.order {
+                BitOrder::Lsb0 => bits as u8,
+                BitOrder::Msb0 => (bits << (8 - n)) as u8,
+            });
+        }
+    }
+
+    /// Get `n` (at most 64) bits at the position, which must be in the input.
+    fn bits_at(&self, position: usize, n: usize) -> u64 {
+        debug_assert!(n <= 64 && position + n <= self.input.len() * 8);
+        if n == 0 {
+            return 0;
+        }
+        let input: &[u8] = &self.input;
+        let start = position / 8;
+        let shift = position % 8;
+        let mut word = [0u8; 8];
+        let available = usize::min(input.len() - start, 8);
+        word[..available].copy_from_slice(&input[start..start + available]);
+        match self.order {
+            BitOrder::Lsb0 => {
+                let mut value = u64::from_le_bytes(word) >> shift;
+                if shift + n > 64 {
+                    value |= u64::from(input[start + 8]) << (64 - shift);
+                }
+                if n == 64 {
+                    value
+                } else {
+                    value & ((1 << n) - 1)
+                }
+            }
+            BitOrder::Msb0 => {
+                let mut value = u64::from_be_bytes(word) << shift;
+                if shift + n > 64 {
+                    value |= u64::from(input[start + 8]) >> (8 - shift);
+                }
+                value >> (64 - n)
+            }
+        }
+    }
+
+    /// Get the next `n` (at most 64) bits and remove them.
+    pub fn read_bits(&mut self, n: usize) -> Result<u64, Error> {
+        let value = self.peek_bits(n)?;
+        self.position += n;
+        Ok(value)
+    }
+
+    /// Get the next `n` (at most 8) bytes as the first bytes of a word without removing them,
+    /// wherever the reader is.
+    pub fn peek_word(&self, n: usize) -> Result<[u8; 8], Error> {
+        let bits = self.peek_bits(n * 8)?;
+        Ok(match self.order {
+            BitOrder::Lsb0 => bits.to_le_bytes(),
+            BitOrder::Msb0 if n == 0 => [0; 8],
+            BitOrder::Msb0 => (bits << (64 - n * 8)).to_be_bytes(),
+        })
+    }
+
+    /// Get the next `n` bytes straight from the input without removing them. Only possible if
+    /// the reader is at a byte boundary.
+    pub fn peek_aligned_bytes(&self, n: usize) -> Option<&[u8]> {
+        if self.position % 8 != 0 {
+            return None;
+        }
+        let start = self.position / 8;
+        self.input.get(start..start.checked_add(n)?)
+    }
+
+    /// Get the rest of the input if the reader is at a byte boundary.
+    pub fn peek_aligned_rest(&self) -> Option<&[u8]> {
+        self.peek_aligned_bytes(self.remaining() / 8)
+    }
+
+    /// Get the rest of the input with the `'de` lifetime if the input is borrowed and the
+    /// reader is at a byte boundary.
+    pub fn peek_borrowed_rest(&self) -> Option<&'de [u8]> {
+        match self.input {
+            Cow::Borrowed(input) if self.position % 8 == 0 => Some(&input[self.position / 8..]),
+            _ => None,
+        }
+    }
+
+    /// Fill `bytes` with the next bytes and remove them. If the reader is at a byte boundary,
+    /// the bytes are copied as is. Otherwise they are read a word (8 bytes) at a time.
+    pub fn read_bytes(&mut self, bytes: &mut [u8]) -> Result<(), Error> {
+        if bytes.len() * 8 > self.remaining() {
+            return Err(Error::UnexpectedEOF);
+        }
+        if let Some(input) = self.peek_aligned_bytes(bytes.len()) {
+            bytes.copy_from_slice(input);
+            self.position += bytes.len() * 8;
+            return Ok(());
+        }
+        for chunk in bytes.chunks_mut(8) {
+            let word = self.peek_word(chunk.len())?;
+            chunk.copy_from_slice(&word[..chunk.len()]);
+            self.position += chunk.len() * 8;
+        }
+        Ok(())
+    }
+}
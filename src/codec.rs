@@ -0,0 +1,133 @@
+//! ### Codec
+//! A module with [`RustFrCodec`], a `tokio_util` codec that frames rust-fr messages so that
+//! several of them can be sent over one stream (e.g. a TCP connection). Requires the `async`
+//! feature. The frames are the ones of the [`framing`] module: the length
+//! of the message in bytes as a varint followed by the message itself, serialized with the
+//! config of the codec.
+//!
+//! ```rust
+//! use futures::{SinkExt, StreamExt};
+//! use rust_fr::codec::RustFrCodec;
+//! use tokio_util::codec::{FramedRead, FramedWrite};
+//!
+//! # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+//! let (client, server) = tokio::io::duplex(64);
+//! let mut sink = FramedWrite::new(client, RustFrCodec::<(u32, String)>::new());
+//! let mut stream = FramedRead::new(server, RustFrCodec::<(u32, String)>::new());
+//!
+//! sink.send((1, "hello".to_string())).await.unwrap();
+//! assert_eq!(stream.next().await.unwrap().unwrap(), (1, "hello".to_string()));
+//! # });
+//! ```
+
+use bytes::{Buf, BufMut, BytesMut};
+use core::{fmt, marker::PhantomData};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio_util::codec::{Decoder, Encoder};
+
+use super::{
+    config::Config,
+    deserializer,
+    error::Error,
+    framing::{self, DEFAULT_MAX_FRAME_LENGTH},
+    serializer,
+};
+
+/// Encodes values of type `T` into frames and decodes frames back into values of type `T`.
+/// The encoder and the decoder must use the same config. Frames longer than the maximum
+/// length are rejected with [`Error::FrameTooLarge`] on both sides, before the decoder buffers
+/// them.
+pub struct RustFrCodec<T> {
+    config: Config,
+    max_frame_length: usize,
+    marker: PhantomData<fn(T) -> T>,
+}
+
+impl<T> RustFrCodec<T> {
+    /// Create a codec with the default config.
+    pub fn new() -> Self {
+        Self::with_config(Config::default())
+    }
+
+    /// Create a codec that serializes the messages with the given config.
+    pub fn with_config(config: Config) -> Self {
+        Self {
+            config,
+            max_frame_length: DEFAULT_MAX_FRAME_LENGTH,
+            marker: PhantomData,
+        }
+    }
+
+    /// Set the length (in bytes, without the length itself) of the largest message the codec
+    /// accepts. Defaults to [`framing::DEFAULT_MAX_FRAME_LENGTH`].
+    pub fn max_frame_length(mut self, max_frame_length: usize) -> Self {
+        self.max_frame_length = max_frame_length;
+        self
+    }
+
+    /// Check that a message of `len` bytes fits in a frame.
+    fn check_length(&self, len: usize) -> Result<(), Error> {
+        if len > self.max_frame_length {
+            return Err(Error::FrameTooLarge(len, self.max_frame_length));
+        }
+        Ok(())
+    }
+}
+
+impl<T> Default for RustFrCodec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Clone for RustFrCodec<T> {
+    fn clone(&self) -> Self {
+        Self {
+            config: self.config,
+            max_frame_length: self.max_frame_length,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> fmt::Debug for RustFrCodec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RustFrCodec")
+            .field("config", &self.config)
+            .field("max_frame_length", &self.max_frame_length)
+            .finish()
+    }
+}
+
+impl<T: Serialize> Encoder<T> for RustFrCodec<T> {
+    type Error = Error;
+
+    fn encode(&mut self, item: T, dst: &mut BytesMut) -> Result<(), Error> {
+        let bytes = serializer::to_bytes_with_config(&item, self.config)?;
+        self.check_length(bytes.len())?;
+        let (length, n) = framing::encode_length(bytes.len());
+        dst.reserve(n + bytes.len());
+        dst.put_slice(&length[..n]);
+        dst.put_slice(&bytes);
+        Ok(())
+    }
+}
+
+impl<T: DeserializeOwned> Decoder for RustFrCodec<T> {
+    type Item = T;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<T>, Error> {
+        let Some((len, n)) = framing::decode_length(src)? else {
+            return Ok(None);
+        };
+        self.check_length(len)?;
+        if src.len() < n + len {
+            src.reserve(n + len - src.len());
+            return Ok(None);
+        }
+        src.advance(n);
+        let frame = src.split_to(len);
+        deserializer::from_bytes_with_config(&frame, self.config).map(Some)
+    }
+}
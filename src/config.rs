@@ -0,0 +1,289 @@
+//! ### Config
+//! A module for the options that tweak the encoding. The default [`Config`] produces the
+//! format described in the README; every other option is opt-in and must be used on both
+//! sides, i.e. data serialized with a given config must be deserialized with the same config.
+//!
+//! ```rust
+//! use rust_fr::{config::Config, deserializer, serializer};
+//!
+//! let config = Config::new().length_prefixed(true);
+//! let bytes = serializer::to_bytes_with_config(&vec![1u8, 2, 3], config).unwrap();
+//! let values = deserializer::from_bytes_with_config::<Vec<u8>>(&bytes, config).unwrap();
+//! assert_eq!(values, vec![1, 2, 3]);
+//! ```
+
+/// The options used by the serializer and the deserializer. Construct it with
+/// [`Config::new`] (or [`Default::default`]) and chain the option methods on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Config {
+    pub(crate) length_prefixed: bool,
+    pub(crate) byte_aligned: bool,
+    // unset: write no header, read one if the input starts with the magic bytes.
+    pub(crate) envelope: Option<bool>,
+    pub(crate) checksum: Checksum,
+    pub(crate) compression: Compression,
+    pub(crate) intern_field_names: bool,
+    pub(crate) packed_sequences: bool,
+    pub(crate) compact_floats: bool,
+    pub(crate) nan_policy: NanPolicy,
+    pub(crate) normalize_negative_zero: bool,
+    pub(crate) canonical: bool,
+    pub(crate) endian: Endian,
+    pub(crate) bit_order: BitOrder,
+    pub(crate) unambiguous: bool,
+}
+
+impl Config {
+    /// Create the default config.
+    pub const fn new() -> Self {
+        Self {
+            length_prefixed: false,
+            byte_aligned: false,
+            envelope: None,
+            checksum: Checksum::None,
+            compression: Compression::None,
+            intern_field_names: false,
+            packed_sequences: false,
+            compact_floats: false,
+            nan_policy: NanPolicy::Preserve,
+            normalize_negative_zero: false,
+            canonical: false,
+            endian: Endian::Little,
+            bit_order: BitOrder::Lsb0,
+            unambiguous: false,
+        }
+    }
+
+    /// Write the element count of sequences and maps up front when it is known. Such
+    /// sequences and maps are written without any per-element delimiters and the deserializer
+    /// reports their exact length through `size_hint`. Sequences and maps of unknown length
+    /// fall back to delimiters.
+    pub const fn length_prefixed(mut self, enabled: bool) -> Self {
+        self.length_prefixed = enabled;
+        self
+    }
+
+    /// Keep every value at a byte boundary: delimiters are written as whole bytes and bools
+    /// (as well as the flags of length-prefixed sequences and maps) take a whole byte
+    /// (0 -> false, 1 -> true). The output is larger but the deserializer can read integers,
+    /// strings and bytes straight from the input instead of reassembling them bit by bit.
+    pub const fn byte_aligned(mut self, enabled: bool) -> Self {
+        self.byte_aligned = enabled;
+        self
+    }
+
+    /// Write a header in front of the payload: magic bytes, the format version and flags
+    /// recording the options used. The deserializer recognizes the header whatever its config
+    /// is and decodes the payload with the options of the header instead, returning
+    /// [`Error::UnsupportedVersion`](crate::error::Error::UnsupportedVersion) for versions it
+    /// doesn't know. With this option, the deserializer also rejects input without a header.
+    ///
+    /// Disabling the option explicitly makes the deserializer read a bare payload even if it
+    /// starts with the magic bytes, e.g. a message that starts with the u32 `0x5246_52F5`.
+    pub const fn envelope(mut self, enabled: bool) -> Self {
+        self.envelope = Some(enabled);
+        self
+    }
+
+    /// Append a checksum of the serialized bytes (the envelope header, if any, and the
+    /// payload) after the payload. The deserializer verifies it before decoding anything and
+    /// returns [`Error::ChecksumMismatch`](crate::error::Error::ChecksumMismatch) if the bytes
+    /// were corrupted. With an envelope, the deserializer also rejects headers without a
+    /// checksum if its own config asks for one.
+    pub const fn checksum(mut self, checksum: Checksum) -> Self {
+        self.checksum = checksum;
+        self
+    }
+
+    /// Compress the payload (everything but the envelope header and the checksum). The
+    /// checksum covers the compressed bytes. Compression needs an intermediate buffer so
+    /// [`to_slice`](crate::serializer::to_slice) and
+    /// [`serialized_size`](crate::serializer::serialized_size) allocate with it and the
+    /// deserializer can't borrow strings and bytes from the input.
+    pub const fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Write each field name of structs (and struct variants) in full only the first time
+    /// it occurs in a message: 0 + str(). Later occurrences refer back to it by its index in
+    /// the order of first occurrence: 1 + varint(index). Field names keep describing the data
+    /// while a sequence of structs pays for them only once.
+    pub const fn intern_field_names(mut self, enabled: bool) -> Self {
+        self.intern_field_names = enabled;
+        self
+    }
+
+    /// Write sequences of known length whose elements are all of one fixed-width primitive
+    /// type (bool, integers, floats, char or newtypes of them, but not `f16`) as
+    /// a packed run: the count followed by the raw values without delimiters, bools taking a
+    /// single bit each (even if the config is byte-aligned; the run is padded to a byte
+    /// boundary then). Every sequence (but not tuples) starts with a kind tag telling whether
+    /// it is packed. A sequence whose elements change their type is written unpacked.
+    pub const fn packed_sequences(mut self, enabled: bool) -> Self {
+        self.packed_sequences = enabled;
+        self
+    }
+
+    /// Write every f32 and f64 in its shortest lossless representation, preceded by a 2-bit
+    /// tag (a whole byte if the config is byte-aligned): 0 -> an integer (zigzag varint),
+    /// 1 -> f16, 2 -> f32, 3 -> f64. Values round-trip bit for bit (including -0.0 and NaN
+    /// payloads). Floats in the packed runs of [`Config::packed_sequences`] keep their width.
+    pub const fn compact_floats(mut self, enabled: bool) -> Self {
+        self.compact_floats = enabled;
+        self
+    }
+
+    /// Choose what the serializer does with NaN values of f32 and f64 (including the ones in
+    /// packed runs and `f16`). See [`NanPolicy`]. Only affects serialization.
+    pub const fn nan_policy(mut self, policy: NanPolicy) -> Self {
+        self.nan_policy = policy;
+        self
+    }
+
+    /// Serialize -0.0 as +0.0 so that equal values produce equal bytes. Only affects
+    /// serialization.
+    pub const fn normalize_negative_zero(mut self, enabled: bool) -> Self {
+        self.normalize_negative_zero = enabled;
+        self
+    }
+
+    /// Serialize maps deterministically: their entries are buffered and written sorted by the
+    /// encoding of their keys (as a length-prefixed map in length-prefixed mode, whatever
+    /// the map says about its length), so that equal maps produce equal bytes whatever their
+    /// iteration order. The serializer returns
+    /// [`Error::DuplicateKey`](crate::error::Error::DuplicateKey) for keys that encode the
+    /// same, and the deserializer rejects maps whose keys are not strictly sorted. Structs
+    /// keep the order of their fields. Can't be combined with
+    /// [`Config::intern_field_names`] since the keys are encoded on their own.
+    pub const fn canonical(mut self, enabled: bool) -> Self {
+        self.canonical = enabled;
+        self
+    }
+
+    /// Choose the byte order of all fixed-width numbers: integers, floats (including the
+    /// ones of compact floats and `f16`), char, enum variant indices and the
+    /// values of packed runs. Varints are LEB128 whatever the byte order is.
+    pub const fn endian(mut self, endian: Endian) -> Self {
+        self.endian = endian;
+        self
+    }
+
+    /// Choose how the bits are packed into bytes. See [`BitOrder`]. Values that start at a
+    /// byte boundary (such as the bytes of strings or the numbers of a byte-aligned config)
+    /// end up as the same bytes in both orders.
+    pub const fn bit_order(mut self, order: BitOrder) -> Self {
+        self.bit_order = order;
+        self
+    }
+
+    /// Write a SeqValue delimiter in front of the first element of delimited sequences too and
+    /// a bool in front of options (None -> false, Some -> true + value). Without it, a first
+    /// element or an option value whose first bits read as the Seq or Unit delimiter (such as
+    /// `vec![3u8]` or `Some(2u8)`) decodes wrong. The default encoding leaves it out to stay
+    /// compatible with data written by earlier versions.
+    pub const fn unambiguous(mut self, enabled: bool) -> Self {
+        self.unambiguous = enabled;
+        self
+    }
+}
+
+/// The order of the bits within a byte. See [`Config::bit_order`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BitOrder {
+    /// Bits fill each byte from its least significant bit and values are written least
+    /// significant bit first, e.g. the delimiter 011 followed by a 1 bit is 0b0000_1011.
+    #[default]
+    Lsb0,
+    /// Bits fill each byte from its most significant bit and values are written most
+    /// significant bit first, as in most hardware bitstreams, e.g. the delimiter 011
+    /// followed by a 1 bit is 0b0111_0000.
+    Msb0,
+}
+
+/// The byte order of fixed-width numbers. See [`Config::endian`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Endian {
+    /// Least significant byte first.
+    #[default]
+    Little,
+    /// Most significant byte first (network byte order).
+    Big,
+}
+
+impl Endian {
+    /// Convert Little Endian bytes to this byte order, or bytes in this byte order back to
+    /// Little Endian.
+    pub(crate) fn order<const N: usize>(self, mut bytes: [u8; N]) -> [u8; N] {
+        if self == Endian::Big {
+            bytes.reverse();
+        }
+        bytes
+    }
+}
+
+/// What the serializer does with NaN values. See [`Config::nan_policy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NanPolicy {
+    /// Write NaNs as they are, payload included.
+    #[default]
+    Preserve,
+    /// Write every NaN as the same quiet NaN (0x7FC00000 for f32, 0x7FF8000000000000 for f64)
+    /// so that equal records produce equal bytes.
+    Canonicalize,
+    /// Return [`Error::NanNotAllowed`](crate::error::Error::NanNotAllowed) for NaNs.
+    Reject,
+}
+
+/// The compression of the payload. See [`Config::compression`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    /// No compression.
+    #[default]
+    None,
+    /// LZ4 block compression (`lz4_flex`), preceded by the uncompressed length as a
+    /// Little Endian u32. Requires the `lz4` feature.
+    #[cfg(feature = "lz4")]
+    Lz4,
+}
+
+/// The checksum appended to the serialized bytes. See [`Config::checksum`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Checksum {
+    /// No checksum.
+    #[default]
+    None,
+    /// CRC-32C (Castagnoli), Little Endian (4 bytes). Requires the `checksum` feature.
+    #[cfg(feature = "checksum")]
+    Crc32c,
+    /// xxHash64 with seed 0, Little Endian (8 bytes). Requires the `checksum` feature.
+    #[cfg(feature = "checksum")]
+    XxHash64,
+}
+
+impl Checksum {
+    /// Get the number of bytes the checksum takes.
+    pub(crate) const fn size(self) -> usize {
+        match self {
+            Checksum::None => 0,
+            #[cfg(feature = "checksum")]
+            Checksum::Crc32c => 4,
+            #[cfg(feature = "checksum")]
+            Checksum::XxHash64 => 8,
+        }
+    }
+
+    /// Compute the checksum of the bytes.
+    #[cfg_attr(not(feature = "checksum"), allow(unused_variables))]
+    pub(crate) fn compute(self, bytes: &[u8]) -> u64 {
+        #[cfg(feature = "checksum")]
+        const CRC32C: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_ISCSI);
+        match self {
+            Checksum::None => 0,
+            #[cfg(feature = "checksum")]
+            Checksum::Crc32c => u64::from(CRC32C.checksum(bytes)),
+            #[cfg(feature = "checksum")]
+            Checksum::XxHash64 => xxhash_rust::xxh64::xxh64(bytes, 0),
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//! ### Delta
+//! A helper for `#[serde(with = "rust_fr::delta")]` that encodes a sequence of integers as the
+//! differences between consecutive elements instead of the elements themselves. Mostly
+//! monotonic sequences (timestamps, sorted ids) then cost a byte or two per element.
+//!
+//! The sequence is written as a blob of zigzag encoded varints: varint(len) followed by
+//! varint(zigzag(element_1 - 0)) + varint(zigzag(element_2 - element_1)) + ... with wrapping
+//! arithmetic on 64 bits, so any sequence round-trips. rust-fr writes the blob as is (it
+//! delimits itself); other formats get it as bytes.
+//!
+//! ```rust
+//! use rust_fr::{deserializer, serializer};
+//!
+//! #[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq)]
+//! struct Series {
+//!     #[serde(with = "rust_fr::delta")]
+//!     timestamps: Vec<u64>,
+//! }
+//!
+//! let series = Series {
+//!     timestamps: (0..100).map(|i| 1_700_000_000_000 + i * 1000).collect(),
+//! };
+//! let bytes = serializer::to_bytes(&series).unwrap();
+//! assert!(bytes.len() < 250);
+//! assert_eq!(deserializer::from_bytes::<Series>(&bytes).unwrap(), series);
+//! ```
+
+use alloc::vec::Vec;
+use core::{fmt, marker::PhantomData};
+use serde::{
+    de::{self, Unexpected},
+    Deserializer, Serialize, Serializer,
+};
+
+/// The name of the newtype struct the blob is wrapped in, so that rust-fr can recognize it.
+pub(crate) const TOKEN: &str = "$rust_fr::private::Delta";
+
+/// The integer types that can be delta encoded.
+pub trait Integer: Copy + sealed::Sealed {
+    /// Get the value as 64 bits (sign extended for signed types).
+    fn to_u64(self) -> u64;
+    /// Get the value back from 64 bits, if it is in the range of the type.
+    fn from_u64(value: u64) -> Option<Self>;
+}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+macro_rules! impl_integer {
+    ($wide:ty => $($ty:ty),*) => {
+        $(
+            impl sealed::Sealed for $ty {}
+            impl Integer for $ty {
+                fn to_u64(self) -> u64 {
+                    self as $wide as u64
+                }
+                fn from_u64(value: u64) -> Option<Self> {
+                    <$ty>::try_from(value as $wide).ok()
+                }
+            }
+        )*
+    };
+}
+impl_integer!(u64 => u8, u16, u32, u64, usize);
+impl_integer!(i64 => i8, i16, i32, i64, isize);
+
+/// Serialize the values as deltas.
+pub fn serialize<T, S>(values: &[T], serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Integer,
+    S: Serializer,
+{
+    let mut blob = Vec::with_capacity(values.len() + 1);
+    write_varint(&mut blob, values.len() as u64);
+    let mut previous = 0u64;
+    for value in values {
+        let value = value.to_u64();
+        let delta = value.wrapping_sub(previous) as i64;
+        write_varint(&mut blob, ((delta << 1) ^ (delta >> 63)) as u64);
+        previous = value;
+    }
+    serializer.serialize_newtype_struct(TOKEN, &Blob(&blob))
+}
+
+/// Deserialize values serialized with [`serialize`].
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    T: Integer,
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_newtype_struct(TOKEN, DeltaVisitor(PhantomData))
+}
+
+/// The blob, written as bytes.
+struct Blob<'a>(&'a [u8]);
+
+impl Serialize for Blob<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+struct DeltaVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Integer> de::Visitor<'de> for DeltaVisitor<T> {
+    type Value = Vec<T>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a delta encoded sequence of integers")
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_bytes(self)
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        decode(v).ok_or_else(|| E::invalid_value(Unexpected::Bytes(v), &self))
+    }
+
+    // for formats that hand bytes out as a sequence (e.g. JSON).
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: de::SeqAccess<'de>,
+    {
+        let mut blob = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(byte) = seq.next_element()? {
+            blob.push(byte);
+        }
+        self.visit_bytes(&blob)
+    }
+}
+
+/// Write a variable length unsigned integer (LEB128) to the blob.
+fn write_varint(blob: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        blob.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    blob.push(value as u8);
+}
+
+/// Read a variable length unsigned integer (LEB128) from the start of the blob.
+fn read_varint(blob: &mut &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = blob.split_first()?;
+        *blob = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// Decode the values of a blob. `None` if the blob is malformed or a value is out of range.
+fn decode<T: Integer>(mut blob: &[u8]) -> Option<Vec<T>> {
+    let len = usize::try_from(read_varint(&mut blob)?).ok()?;
+    let mut values = Vec::with_capacity(len.min(blob.len()));
+    let mut previous = 0u64;
+    for _ in 0..len {
+        let zigzag = read_varint(&mut blob)?;
+        let delta = (zigzag >> 1) as i64 ^ -((zigzag & 1) as i64);
+        previous = previous.wrapping_add(delta as u64);
+        values.push(T::from_u64(previous)?);
+    }
+    blob.is_empty().then_some(values)
+}
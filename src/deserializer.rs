@@ -4,22 +4,68 @@
 //!
 //! To use the deserializer, you need to call the [`from_bytes`] function which takes in
 //! the bytes and a type. The type must implement the `Deserialize` trait from the serde library.
-//! It returns a Result with the deserialized data or an error.
+//! It returns a Result with the deserialized data or an error. A [`Deserializer`] can also
+//! be driven directly, e.g. to decode several values from the same bytes.
 
-use bitvec::{prelude as bv, slice::BitSlice, view::BitView};
+use alloc::{
+    borrow::Cow,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::marker::PhantomData;
 use serde::{
-    de::{EnumAccess, IntoDeserializer, MapAccess, SeqAccess, VariantAccess},
-    Deserialize, Deserializer,
+    de::{
+        self, value::StrDeserializer, DeserializeSeed, Deserializer as _, EnumAccess,
+        IntoDeserializer, MapAccess, SeqAccess, VariantAccess,
+    },
+    Deserialize,
 };
+#[cfg(feature = "std")]
+use {serde::de::DeserializeOwned, std::io::Read};
 
-use super::{error::Error, serializer::Delimiter};
+use super::{
+    bits::BitReader,
+    config::Config,
+    delta, envelope,
+    error::Error,
+    float,
+    packed::{Kind, Primitive},
+    serializer::Delimiter,
+};
 
-// Internal struct that handles the deserialization of the data.
-// It has a few methods that allows us to peek and eat bytes from the data.
-// It also has methods to parse some data into the required type.
+/// The deserializer of the format. Create one with [`Deserializer::from_slice`] (or
+/// [`Deserializer::with_config`]) and deserialize values with `&mut Deserializer`, which
+/// implements `serde::Deserializer`.
+/// - The format is not self-describing: values must be deserialized as the same types, in the
+///   same order and with the same [`Config`] they were serialized with. `deserialize_any` is
+///   not supported.
+/// - Strings and bytes are borrowed from the input when possible, hence the `'de` lifetime.
+/// - Nothing checks that the whole input was used; call [`Deserializer::end`] for that.
+/// - The input is the bare payload; the envelope header of [`Config::envelope`] is only
+///   handled by the functions of this module.
+///
+/// ```rust
+/// use rust_fr::{deserializer::Deserializer, serializer::Serializer};
+/// use serde::{Deserialize, Serialize};
+///
+/// let mut serializer = Serializer::new();
+/// 1u8.serialize(&mut serializer).unwrap();
+/// "two".serialize(&mut serializer).unwrap();
+/// let bytes = serializer.into_inner().unwrap();
+///
+/// let mut deserializer = Deserializer::from_slice(&bytes);
+/// assert_eq!(u8::deserialize(&mut deserializer).unwrap(), 1);
+/// assert_eq!(<&str>::deserialize(&mut deserializer).unwrap(), "two");
+/// deserializer.end().unwrap();
+/// ```
 #[derive(Debug)]
-struct CustomDeserializer<'de> {
-    data: &'de bv::BitSlice<u8, bv::Lsb0>,
+pub struct Deserializer<'de> {
+    data: BitReader<'de>,
+    config: Config,
+    // reused buffer for the strings and bytes that can't be borrowed from the input.
+    scratch: Vec<u8>,
+    // the field names seen so far if the config interns them.
+    field_names: Vec<String>,
 }
 
 /// The function to deserialize (serialized) bytes back into data. `T` must implement the `Deserialize` trait
@@ -29,233 +75,543 @@ pub fn from_bytes<'de, T>(bytes: &'de [u8]) -> Result<T, Error>
 where
     T: Deserialize<'de>,
 {
-    let mut deserializer = CustomDeserializer {
-        data: bytes.view_bits(),
-    };
-    let deserialized = T::deserialize(&mut deserializer)?;
+    from_bytes_with_config(bytes, Config::default())
+}
+
+/// The same as [`from_bytes`] but for bytes that were serialized with the given [`Config`].
+pub fn from_bytes_with_config<'de, T>(bytes: &'de [u8], config: Config) -> Result<T, Error>
+where
+    T: Deserialize<'de>,
+{
+    from_bytes_seed_with_config(PhantomData::<T>, bytes, config)
+}
+
+/// Deserialize bytes into an existing value with `Deserialize::deserialize_in_place`. Types
+/// that support it (e.g. `String`, `Vec<T>` and, with serde's `deserialize_in_place`
+/// feature, derived structs) reuse their allocations instead of building a new value.
+pub fn from_bytes_into<'de, T>(place: &mut T, bytes: &'de [u8]) -> Result<(), Error>
+where
+    T: Deserialize<'de>,
+{
+    from_bytes_into_with_config(place, bytes, Config::default())
+}
+
+/// The same as [`from_bytes_into`] but for bytes that were serialized with the given [`Config`].
+pub fn from_bytes_into_with_config<'de, T>(
+    place: &mut T,
+    bytes: &'de [u8],
+    config: Config,
+) -> Result<(), Error>
+where
+    T: Deserialize<'de>,
+{
+    let (config, bytes) = envelope::open(bytes, config)?;
+    let mut deserializer = Deserializer::from_input(bytes, config);
+    T::deserialize_in_place(&mut deserializer, place)
+}
+
+/// Deserialize bytes with a `DeserializeSeed`, i.e. a deserializer that carries state such
+/// as an interner or an arena. [`from_bytes`] is the same as using `PhantomData<T>` as the seed.
+pub fn from_bytes_seed<'de, S>(seed: S, bytes: &'de [u8]) -> Result<S::Value, Error>
+where
+    S: DeserializeSeed<'de>,
+{
+    from_bytes_seed_with_config(seed, bytes, Config::default())
+}
+
+/// The same as [`from_bytes_seed`] but for bytes that were serialized with the given [`Config`].
+pub fn from_bytes_seed_with_config<'de, S>(
+    seed: S,
+    bytes: &'de [u8],
+    config: Config,
+) -> Result<S::Value, Error>
+where
+    S: DeserializeSeed<'de>,
+{
+    let (config, bytes) = envelope::open(bytes, config)?;
+    let mut deserializer = Deserializer::from_input(bytes, config);
+    let deserialized = seed.deserialize(&mut deserializer)?;
     Ok(deserialized)
 }
 
-impl<'de> CustomDeserializer<'de> {
-    /// Get 'n' bits from end of the data.
-    /// Example: If the data is 0b10101010 and n is 3, the result will be 0b010.
-    fn _peek_n_bits(&self, size: usize) -> Result<&BitSlice<u8>, Error> {
-        let len = self.data.len();
-        if size > len {
-            return Err(Error::NLargerThanLength(size, self.data.len()));
-        }
-        self.data.get(..size).ok_or(Error::NoByte)
+/// Deserialize data from a reader. The whole reader is read into memory first since the
+/// format can't tell where a value ends without decoding it; the data can't borrow from the
+/// input, hence `T` must implement `DeserializeOwned`. Requires the `std` feature.
+#[cfg(feature = "std")]
+pub fn from_reader<R, T>(reader: R) -> Result<T, Error>
+where
+    R: Read,
+    T: DeserializeOwned,
+{
+    from_reader_with_config(reader, Config::default())
+}
+
+/// The same as [`from_reader`] but for bytes that were serialized with the given [`Config`].
+#[cfg(feature = "std")]
+pub fn from_reader_with_config<R, T>(reader: R, config: Config) -> Result<T, Error>
+where
+    R: Read,
+    T: DeserializeOwned,
+{
+    from_reader_seed_with_config(PhantomData::<T>, reader, config)
+}
+
+/// The [`from_bytes_seed`] equivalent of [`from_reader`].
+#[cfg(feature = "std")]
+pub fn from_reader_seed<R, S, T>(seed: S, reader: R) -> Result<T, Error>
+where
+    R: Read,
+    S: for<'de> DeserializeSeed<'de, Value = T>,
+{
+    from_reader_seed_with_config(seed, reader, Config::default())
+}
+
+/// The same as [`from_reader_seed`] but for bytes that were serialized with the given [`Config`].
+#[cfg(feature = "std")]
+pub fn from_reader_seed_with_config<R, S, T>(
+    seed: S,
+    mut reader: R,
+    config: Config,
+) -> Result<T, Error>
+where
+    R: Read,
+    S: for<'de> DeserializeSeed<'de, Value = T>,
+{
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    from_bytes_seed_with_config(seed, &bytes, config)
+}
+
+/// The async equivalent of [`from_reader`]: the whole reader is read into memory first.
+/// Requires the `async` feature.
+#[cfg(feature = "async")]
+pub async fn from_async_reader<R, T>(reader: R) -> Result<T, Error>
+where
+    R: tokio::io::AsyncRead + Unpin,
+    T: DeserializeOwned,
+{
+    from_async_reader_with_config(reader, Config::default()).await
+}
+
+/// The same as [`from_async_reader`] but for bytes that were serialized with the given
+/// [`Config`].
+#[cfg(feature = "async")]
+pub async fn from_async_reader_with_config<R, T>(mut reader: R, config: Config) -> Result<T, Error>
+where
+    R: tokio::io::AsyncRead + Unpin,
+    T: DeserializeOwned,
+{
+    use tokio::io::AsyncReadExt;
+
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes).await?;
+    from_bytes_with_config(&bytes, config)
+}
+
+/// The async equivalent of [`from_reader`] for a `futures::io::AsyncRead` reader: the whole
+/// reader is read into memory first. Requires the `futures-io` feature.
+#[cfg(feature = "futures-io")]
+pub async fn from_futures_reader<R, T>(reader: R) -> Result<T, Error>
+where
+    R: futures_util::io::AsyncRead + Unpin,
+    T: DeserializeOwned,
+{
+    from_futures_reader_with_config(reader, Config::default()).await
+}
+
+/// The same as [`from_futures_reader`] but for bytes that were serialized with the given
+/// [`Config`].
+#[cfg(feature = "futures-io")]
+pub async fn from_futures_reader_with_config<R, T>(
+    mut reader: R,
+    config: Config,
+) -> Result<T, Error>
+where
+    R: futures_util::io::AsyncRead + Unpin,
+    T: DeserializeOwned,
+{
+    use futures_util::io::AsyncReadExt;
+
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes).await?;
+    from_bytes_with_config(&bytes, config)
+}
+
+impl<'de> Deserializer<'de> {
+    /// Create a deserializer for bytes serialized with the default config.
+    pub fn from_slice(bytes: &'de [u8]) -> Self {
+        Self::with_config(bytes, Config::default())
     }
 
-    /// Get the first byte from the data.
-    pub fn peek_byte(&self) -> Result<u8, Error> {
-        let bits = self._peek_n_bits(8)?;
-        let mut byte = 0u8;
-        for (i, bit) in bits.iter().enumerate() {
-            if *bit {
-                byte |= 1 << i;
-            }
+    /// Create a deserializer for bytes serialized with the given config.
+    pub fn with_config(bytes: &'de [u8], config: Config) -> Self {
+        Self::from_input(bytes, config)
+    }
+
+    /// Create a deserializer for borrowed or owned bytes. Only borrowed bytes can be borrowed
+    /// by the deserialized data.
+    fn from_input(bytes: impl Into<Cow<'de, [u8]>>, config: Config) -> Self {
+        Self {
+            data: BitReader::new(bytes, config.bit_order),
+            config,
+            scratch: Vec::new(),
+            field_names: Vec::new(),
         }
-        Ok(byte)
     }
 
-    /// Peek the next token from the data.
-    pub fn peek_token(&self, token: Delimiter) -> Result<bool, Error> {
-        let bits = match token {
-            Delimiter::String => self._peek_n_bits(8)?,
-            Delimiter::Byte => self._peek_n_bits(8)?,
-            Delimiter::Map => self._peek_n_bits(8)?,
-            _ => self._peek_n_bits(3)?,
-        };
-        let mut byte = 0u8;
-        for (i, bit) in bits.iter().enumerate() {
-            if *bit {
-                byte |= 1 << i;
-            }
+    /// Check that all the input was deserialized, i.e. that only the zero padding of the
+    /// last byte is left.
+    pub fn end(&self) -> Result<(), Error> {
+        let remaining = self.data.remaining();
+        if remaining >= 8 || self.data.peek_bits(remaining)? != 0 {
+            return Err(Error::TrailingData(remaining));
         }
-        if byte == token as u8 {
-            Ok(true)
-        } else {
-            Ok(false)
+        Ok(())
+    }
+
+    /// Get the number of bits a token takes. If the config is byte-aligned, every token is a whole byte.
+    fn token_bits(&self, token: Delimiter) -> usize {
+        match token {
+            _ if self.config.byte_aligned => 8,
+            Delimiter::String | Delimiter::Byte | Delimiter::Map => 8,
+            _ => 3,
         }
     }
 
+    /// Peek the next token from the data.
+    fn peek_token(&self, token: Delimiter) -> Result<bool, Error> {
+        let byte = self.data.peek_bits(self.token_bits(token))?;
+        Ok(byte == token as u64)
+    }
+
     /// Grab the next bit from the data and remove it.
-    pub fn eat_bit(&mut self) -> Result<bool, Error> {
-        let bit = *self._peek_n_bits(1)?.get(0).ok_or(Error::NoBit)?;
-        self.data = &self.data[1..];
-        Ok(bit)
+    fn eat_bit(&mut self) -> Result<bool, Error> {
+        Ok(self.data.read_bits(1)? == 1)
     }
 
     /// Grab the next byte from the data and remove it.
-    pub fn eat_byte(&mut self) -> Result<u8, Error> {
-        let byte = self.peek_byte()?;
-        self.data = &self.data[8..];
-        Ok(byte)
-    }
-
-    /// Grab the next 'n' bytes from the data and remove them.
-    pub fn eat_bytes(&mut self, n: usize) -> Result<Vec<u8>, Error> {
-        let bits = &self.data[..n * 8];
-        let mut bytes = Vec::new();
-        self.data = &self.data[n * 8..];
-        for i in 0..n {
-            let mut byte = 0u8;
-            for (j, bit) in bits[i * 8..(i + 1) * 8].iter().enumerate() {
-                if *bit {
-                    byte |= 1 << j;
-                }
-            }
-            bytes.push(byte);
-        }
-        Ok(bytes)
+    fn eat_byte(&mut self) -> Result<u8, Error> {
+        Ok(self.data.read_bits(8)? as u8)
+    }
+
+    /// Grab the next 'N' bytes from the data as an array and remove them.
+    fn eat_array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut array = [0u8; N];
+        self.data.read_bytes(&mut array)?;
+        Ok(array)
+    }
+
+    /// Grab the next fixed-width number from the data as its Little Endian bytes and remove it.
+    fn eat_number<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        Ok(self.config.endian.order(self.eat_array()?))
     }
 
     /// Grab the next token from the data and remove it.
-    pub fn eat_token(&mut self, token: Delimiter) -> Result<(), Error> {
-        let bits_to_munch = match token {
-            Delimiter::String => 8,
-            Delimiter::Byte => 8,
-            Delimiter::Map => 8,
-            _ => 3,
-        };
-        if self.data.len() < bits_to_munch {
-            return Err(Error::UnexpectedEOF);
-        }
-        self.data = &self.data[bits_to_munch..];
-        Ok(())
+    fn eat_token(&mut self, token: Delimiter) -> Result<(), Error> {
+        self.data.advance(self.token_bits(token))
     }
 
-    /// Parser Methods
+    // Parser Methods
 
-    /// Parses a boolean value from the input.
-    pub fn parse_bool(&mut self) -> Result<bool, Error> {
-        self.eat_bit()
+    /// Parses a boolean value from the input. If the config is byte-aligned, the boolean is a whole byte.
+    fn parse_bool(&mut self) -> Result<bool, Error> {
+        if !self.config.byte_aligned {
+            return self.eat_bit();
+        }
+        match self.eat_byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(Error::ConversionError),
+        }
     }
     /// Parses an unsigned integer value from the input.
-    pub fn parse_unsigned<T>(&mut self) -> Result<T, Error>
+    fn parse_unsigned<T>(&mut self) -> Result<T, Error>
     where
         T: TryFrom<u8> + TryFrom<u16> + TryFrom<u32> + TryFrom<u64>,
     {
-        let length = std::mem::size_of::<T>();
-        if self.data.len() < length {
-            return Err(Error::UnexpectedEOF);
-        }
-        match length {
-            1 => {
-                let byte = self.eat_byte()?;
-                u8::from_le_bytes([byte])
-                    .try_into()
-                    .map_err(|_| Error::ConversionError)
-            }
-            2 => {
-                let bytes = self.eat_bytes(length)?;
-                u16::from_le_bytes([bytes[0], bytes[1]])
-                    .try_into()
-                    .map_err(|_| Error::ConversionError)
-            }
-            4 => {
-                let bytes = self.eat_bytes(length)?;
-                u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
-                    .try_into()
-                    .map_err(|_| Error::ConversionError)
-            }
-            8 => {
-                let bytes = self.eat_bytes(length)?;
-                u64::from_le_bytes([
-                    bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
-                ])
+        match core::mem::size_of::<T>() {
+            1 => u8::from_le_bytes(self.eat_number()?)
                 .try_into()
-                .map_err(|_| Error::ConversionError)
-            }
+                .map_err(|_| Error::ConversionError),
+            2 => u16::from_le_bytes(self.eat_number()?)
+                .try_into()
+                .map_err(|_| Error::ConversionError),
+            4 => u32::from_le_bytes(self.eat_number()?)
+                .try_into()
+                .map_err(|_| Error::ConversionError),
+            8 => u64::from_le_bytes(self.eat_number()?)
+                .try_into()
+                .map_err(|_| Error::ConversionError),
             _ => Err(Error::InvalidTypeSize),
         }
     }
     /// Parses a signed integer value from the input.
-    pub fn parse_signed<T>(&mut self) -> Result<T, Error>
+    fn parse_signed<T>(&mut self) -> Result<T, Error>
     where
         T: TryFrom<i8> + TryFrom<i16> + TryFrom<i32> + TryFrom<i64>,
     {
-        let length = std::mem::size_of::<T>();
-        if self.data.len() < length {
-            return Err(Error::UnexpectedEOF);
+        match core::mem::size_of::<T>() {
+            1 => i8::from_le_bytes(self.eat_number()?)
+                .try_into()
+                .map_err(|_| Error::ConversionError),
+            2 => i16::from_le_bytes(self.eat_number()?)
+                .try_into()
+                .map_err(|_| Error::ConversionError),
+            4 => i32::from_le_bytes(self.eat_number()?)
+                .try_into()
+                .map_err(|_| Error::ConversionError),
+            8 => i64::from_le_bytes(self.eat_number()?)
+                .try_into()
+                .map_err(|_| Error::ConversionError),
+            _ => Err(Error::InvalidTypeSize),
         }
-        match length {
+    }
+    /// Parses the tag of a compact float and the value if it is written as an integer, an
+    /// f16 or an f32. Returns `None` for tag 3, i.e. if an f64 follows.
+    fn parse_compact_float(&mut self) -> Result<Option<f64>, Error> {
+        let bits = match self.config.byte_aligned {
+            true => 8,
+            false => float::TAG_BITS,
+        };
+        match self.data.read_bits(bits)? {
+            0 => Ok(Some(float::unzigzag(self.parse_varint()?))),
             1 => {
-                let byte = self.eat_byte()?;
-                i8::from_le_bytes([byte])
-                    .try_into()
-                    .map_err(|_| Error::ConversionError)
+                let bits = u16::from_le_bytes(self.eat_number()?);
+                Ok(Some(float::from_f16_bits(bits)))
             }
-            2 => {
-                let bytes = self.eat_bytes(length)?;
-                i16::from_le_bytes([bytes[0], bytes[1]])
-                    .try_into()
-                    .map_err(|_| Error::ConversionError)
-            }
-            4 => {
-                let bytes = self.eat_bytes(length)?;
-                i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
-                    .try_into()
-                    .map_err(|_| Error::ConversionError)
-            }
-            8 => {
-                let bytes = self.eat_bytes(length)?;
-                i64::from_le_bytes([
-                    bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
-                ])
-                .try_into()
-                .map_err(|_| Error::ConversionError)
-            }
-            _ => Err(Error::InvalidTypeSize),
+            2 => Ok(Some(f64::from(f32::from_le_bytes(self.eat_number()?)))),
+            3 => Ok(None),
+            _ => Err(Error::ConversionError),
         }
     }
     /// Parses a 32-bit floating point value from the input.
-    pub fn parse_f32(&mut self) -> Result<f32, Error> {
-        let bytes = self.eat_bytes(4)?;
-        Ok(f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    fn parse_f32(&mut self) -> Result<f32, Error> {
+        if self.config.compact_floats {
+            return match self.parse_compact_float()? {
+                Some(value) => Ok(value as f32),
+                None => Err(Error::ConversionError),
+            };
+        }
+        Ok(f32::from_le_bytes(self.eat_number()?))
     }
     /// Parses a 64-bit floating point value from the input.
-    pub fn parse_f64(&mut self) -> Result<f64, Error> {
-        let bytes = self.eat_bytes(8)?;
-        Ok(f64::from_le_bytes([
-            bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
-        ]))
+    fn parse_f64(&mut self) -> Result<f64, Error> {
+        if self.config.compact_floats {
+            return match self.parse_compact_float()? {
+                Some(value) => Ok(value),
+                None => Ok(f64::from_le_bytes(self.eat_number()?)),
+            };
+        }
+        Ok(f64::from_le_bytes(self.eat_number()?))
     }
     /// Parses a character value from the input.
-    pub fn parse_char(&mut self) -> Result<char, Error> {
+    fn parse_char(&mut self) -> Result<char, Error> {
         let value = self.parse_unsigned::<u32>()?;
-        Ok(std::char::from_u32(value).unwrap())
+        core::char::from_u32(value).ok_or(Error::ConversionError)
+    }
+
+    /// Parses a variable length unsigned integer (LEB128) from the input.
+    fn parse_varint(&mut self) -> Result<u64, Error> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.eat_byte()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(Error::InvalidVarint)
+    }
+
+    /// Parses the blob of a delta encoded sequence (see [`delta`](crate::delta)) into the
+    /// scratch buffer: varint(len) followed by len varints.
+    fn parse_delta(&mut self) -> Result<(), Error> {
+        self.scratch.clear();
+        let len = self.copy_varint()?;
+        for _ in 0..len {
+            self.copy_varint()?;
+        }
+        Ok(())
     }
 
-    /// Parses a string value from the input.
-    pub fn parse_str(&mut self, bytes: &mut Vec<u8>) -> Result<String, Error> {
-        'byteloop: loop {
+    /// Parses a variable length unsigned integer like [`Deserializer::parse_varint`] and
+    /// appends its bytes to the scratch buffer.
+    fn copy_varint(&mut self) -> Result<u64, Error> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
             let byte = self.eat_byte()?;
-            bytes.push(byte);
-            if self.peek_token(Delimiter::String)? {
-                self.eat_token(Delimiter::String)?;
-                break 'byteloop;
+            self.scratch.push(byte);
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
             }
         }
-        String::from_utf8(bytes.clone()).map_err(|_| Error::ConversionError)
+        Err(Error::InvalidVarint)
+    }
+
+    /// Parses the length header of a sequence or a map. Returns `None` if the config is
+    /// not length-prefixed or if the length was not known during serialization.
+    fn parse_length(&mut self) -> Result<Option<usize>, Error> {
+        if !self.config.length_prefixed || !self.parse_bool()? {
+            return Ok(None);
+        }
+        let len = self.parse_varint()?;
+        usize::try_from(len)
+            .map(Some)
+            .map_err(|_| Error::ConversionError)
     }
 
-    /// Parses a byte buffer from the input.
-    pub fn parse_bytes(&mut self, bytes: &mut Vec<u8>) -> Result<(), Error> {
+    /// Parses the bytes up to a (1 byte long) delimiter from the input and eats the delimiter.
+    /// If the input is borrowed and the data is at a byte boundary, the bytes are borrowed from
+    /// the input. Otherwise they are copied into the scratch buffer (a word (8 bytes) at a time
+    /// if the data is not at a byte boundary) and `None` is returned.
+    fn parse_delimited(&mut self, delimiter: Delimiter) -> Result<Option<&'de [u8]>, Error> {
+        let find = |rest: &[u8]| {
+            rest.iter()
+                .position(|byte| *byte == delimiter as u8)
+                .ok_or(Error::ExpectedDelimiter(delimiter))
+        };
+        if let Some(rest) = self.data.peek_borrowed_rest() {
+            let len = find(rest)?;
+            self.data.advance((len + 1) * 8)?;
+            return Ok(Some(&rest[..len]));
+        }
+        self.scratch.clear();
+        if let Some(rest) = self.data.peek_aligned_rest() {
+            let len = find(rest)?;
+            self.scratch.extend_from_slice(&rest[..len]);
+            self.data.advance((len + 1) * 8)?;
+            return Ok(None);
+        }
         loop {
-            if self.peek_token(Delimiter::Byte)? {
-                self.eat_token(Delimiter::Byte)?;
-                break;
+            let n = usize::min(self.data.remaining() / 8, 8);
+            if n == 0 {
+                return Err(Error::ExpectedDelimiter(delimiter));
+            }
+            let word = self.data.peek_word(n)?;
+            match word[..n].iter().position(|byte| *byte == delimiter as u8) {
+                Some(len) => {
+                    self.scratch.extend_from_slice(&word[..len]);
+                    self.data.advance((len + 1) * 8)?;
+                    return Ok(None);
+                }
+                None => {
+                    self.scratch.extend_from_slice(&word[..n]);
+                    self.data.advance(n * 8)?;
+                }
             }
-            let byte = self.eat_byte()?;
-            bytes.push(byte);
         }
-        Ok(())
+    }
+
+    /// Parses a string value from the input. See [`Deserializer::parse_delimited`].
+    fn parse_str(&mut self) -> Result<Option<&'de [u8]>, Error> {
+        self.parse_delimited(Delimiter::String)
+    }
+
+    /// Parses a byte buffer from the input. See [`Deserializer::parse_delimited`].
+    fn parse_bytes(&mut self) -> Result<Option<&'de [u8]>, Error> {
+        self.parse_delimited(Delimiter::Byte)
+    }
+
+    /// Parses a field name of a struct if the config interns them: 1 + varint(index) for a
+    /// field name that was already seen in the message, 0 + str() for a new one which is
+    /// appended to the table.
+    fn parse_field_name<K>(&mut self, seed: K) -> Result<K::Value, Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        if self.parse_bool()? {
+            let index = self.parse_varint()?;
+            let name = usize::try_from(index)
+                .ok()
+                .and_then(|index| self.field_names.get(index))
+                .ok_or(Error::UnknownFieldName(index))?;
+            return seed.deserialize(StrDeserializer::new(name));
+        }
+        let name = match self.parse_str()? {
+            Some(bytes) => core::str::from_utf8(bytes),
+            None => core::str::from_utf8(&self.scratch),
+        }
+        .map_err(|_| Error::ConversionError)?;
+        self.field_names.push(name.to_string());
+        seed.deserialize(StrDeserializer::new(name))
+    }
+
+    /// Parses a sequence (or a tuple if `packable` is not set) with the visitor. In packed
+    /// mode, sequences have a kind tag after the length header, and packed runs of bools are
+    /// padded to a byte boundary if the config is byte-aligned.
+    fn parse_seq<V>(&mut self, visitor: V, packable: bool) -> Result<V::Value, Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        if !self.peek_token(Delimiter::Seq)? {
+            return Err(Error::ExpectedDelimiter(Delimiter::Seq));
+        }
+        self.eat_token(Delimiter::Seq)?;
+        let mut len = self.parse_length()?;
+        let mut packed = None;
+        if packable && self.config.packed_sequences {
+            let kind = self.parse_kind()?;
+            if kind != Kind::Other {
+                if len.is_none() {
+                    let count = self.parse_varint()?;
+                    len = Some(usize::try_from(count).map_err(|_| Error::ConversionError)?);
+                }
+                packed = Some(kind);
+            }
+        }
+        let mut sequence = SequenceDeserializer::new(self, len, packed);
+        let value = visitor.visit_seq(&mut sequence)?;
+        if let Some(len) = len {
+            if sequence.remaining != Some(0) {
+                return Err(Error::LengthMismatch(len, sequence.count));
+            }
+            if packed == Some(Kind::Bool) && self.config.byte_aligned {
+                self.data.advance(self.data.remaining() % 8)?;
+            }
+            return Ok(value);
+        }
+        if !self.peek_token(Delimiter::Seq)? {
+            return Err(Error::ExpectedDelimiter(Delimiter::Seq));
+        }
+        self.eat_token(Delimiter::Seq)?;
+        Ok(value)
+    }
+
+    /// Parses the kind tag of a sequence in packed mode. If the config is byte-aligned, the
+    /// kind tag is a whole byte.
+    fn parse_kind(&mut self) -> Result<Kind, Error> {
+        let bits = match self.config.byte_aligned {
+            true => 8,
+            false => Kind::BITS,
+        };
+        let tag = self.data.read_bits(bits)?;
+        Kind::from_tag(u8::try_from(tag).map_err(|_| Error::ConversionError)?)
+    }
+
+    /// Parses a map (or a struct if `fields` is set) with the visitor.
+    fn parse_map<V>(&mut self, visitor: V, fields: bool) -> Result<V::Value, Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        let len = self.parse_length()?;
+        let mut map = MapDeserializer::new(self, len, fields);
+        let value = visitor.visit_map(&mut map)?;
+        if let Some(len) = len {
+            return match map.remaining {
+                Some(0) => Ok(value),
+                _ => Err(Error::LengthMismatch(len, map.count)),
+            };
+        }
+        if !self.peek_token(Delimiter::Map)? {
+            return Err(Error::ExpectedDelimiter(Delimiter::Map));
+        }
+        self.eat_token(Delimiter::Map)?;
+        Ok(value)
     }
 }
 
-impl<'de, 'a> Deserializer<'de> for &'a mut CustomDeserializer<'de> {
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
     type Error = Error;
 
     /// The data is not self-describing, so we need to use the type to determine how to deserialize it.
@@ -266,7 +622,7 @@ impl<'de, 'a> Deserializer<'de> for &'a mut CustomDeserializer<'de> {
         Err(Error::UnsupportedCall("deserialize_any".to_string()))
     }
 
-    // Primitve Types Deserialization. They are serialized as is (LE byte order).
+    // Primitve Types Deserialization. They are serialized as is (in the byte order of the config).
 
     fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
@@ -342,45 +698,60 @@ impl<'de, 'a> Deserializer<'de> for &'a mut CustomDeserializer<'de> {
     }
 
     /// String Deserialization. They are serialized as bytes + STRING_DELIMITER.
+    /// The string is borrowed from the input if it starts at a byte boundary.
     fn deserialize_str<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        let mut bytes = Vec::new();
-        visitor.visit_str(self.parse_str(&mut bytes)?.as_str())
+        match self.parse_str()? {
+            Some(bytes) => visitor.visit_borrowed_str(
+                core::str::from_utf8(bytes).map_err(|_| Error::ConversionError)?,
+            ),
+            None => visitor.visit_str(
+                core::str::from_utf8(&self.scratch).map_err(|_| Error::ConversionError)?,
+            ),
+        }
     }
+    /// Copied strings are visited as `&str` (from the scratch buffer) so that in-place
+    /// deserialization can reuse the buffer of the existing `String`.
     fn deserialize_string<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        let mut bytes = Vec::new();
-        visitor.visit_string(self.parse_str(&mut bytes)?.to_string())
+        self.deserialize_str(visitor)
     }
 
     /// Byte Deserialization. They are serialized as bytes + BYTE_DELIMITER.
+    /// The bytes are borrowed from the input if they start at a byte boundary.
     fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        let mut bytes = Vec::new();
-        self.parse_bytes(&mut bytes)?;
-        visitor.visit_bytes(&bytes)
+        match self.parse_bytes()? {
+            Some(bytes) => visitor.visit_borrowed_bytes(bytes),
+            None => visitor.visit_bytes(&self.scratch),
+        }
     }
 
     fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        let mut bytes = Vec::new();
-        self.parse_bytes(&mut bytes)?;
-        visitor.visit_byte_buf(bytes)
+        self.deserialize_bytes(visitor)
     }
 
     /// Option Deserialization. They are serialized as None -> unit(), Some -> self.
+    /// If the config is unambiguous; None -> bool(false), Some -> bool(true) + self.
     fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
+        if self.config.unambiguous {
+            return match self.parse_bool()? {
+                true => visitor.visit_some(self),
+                false => visitor.visit_none(),
+            };
+        }
         match self.peek_token(Delimiter::Unit)? {
             true => {
                 self.eat_token(Delimiter::Unit)?;
@@ -418,13 +789,24 @@ impl<'de, 'a> Deserializer<'de> for &'a mut CustomDeserializer<'de> {
     /// - newtype_struct: self
     fn deserialize_newtype_struct<V>(
         self,
-        _name: &'static str,
+        name: &'static str,
         visitor: V,
     ) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        visitor.visit_newtype_struct(self)
+        match name {
+            delta::TOKEN => {
+                self.parse_delta()?;
+                visitor.visit_bytes(&self.scratch)
+            }
+            #[cfg(feature = "f16")]
+            float::F16_TOKEN => {
+                let bits = u16::from_le_bytes(self.eat_number()?);
+                visitor.visit_f64(float::from_f16_bits(bits))
+            }
+            _ => visitor.visit_newtype_struct(self),
+        }
     }
     /// - tuple_struct: seq()
     fn deserialize_tuple_struct<V>(
@@ -436,7 +818,7 @@ impl<'de, 'a> Deserializer<'de> for &'a mut CustomDeserializer<'de> {
     where
         V: serde::de::Visitor<'de>,
     {
-        self.deserialize_seq(visitor)
+        self.parse_seq(visitor, false)
     }
 
     /// Enum Deserialization.
@@ -458,34 +840,21 @@ impl<'de, 'a> Deserializer<'de> for &'a mut CustomDeserializer<'de> {
 
     /// Seq & Map Deserialization.
     /// - seq: SEQ_DELIMITER + value_1 + SEQ_VALUE_DELIMITER + value_2 + SEQ_VALUE_DELIMITER + ... + SEQ_DELIMITER
+    /// - length-prefixed seq: SEQ_DELIMITER + 1 + varint(len) + value_1 + value_2 + ...
+    /// - packed seq: SEQ_DELIMITER + [length header] + kind + [varint(len)] + value_1 + value_2 + ...
     fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        match self.peek_token(Delimiter::Seq)? {
-            true => {
-                self.eat_token(Delimiter::Seq)?;
-                let value = visitor.visit_seq(SequenceDeserializer::new(self))?;
-                if !self.peek_token(Delimiter::Seq)? {
-                    return Err(Error::ExpectedDelimiter(Delimiter::Seq));
-                }
-                self.eat_token(Delimiter::Seq)?;
-                Ok(value)
-            }
-            false => Err(Error::ExpectedDelimiter(Delimiter::Seq)),
-        }
+        self.parse_seq(visitor, true)
     }
     /// - map: key_1 + MAP_KEY_DELIMITER + value_1 + MAP_VALUE_DELIMITER + ... + MAP_DELIMITER
+    /// - length-prefixed map: 1 + varint(len) + key_1 + value_1 + key_2 + value_2 + ...
     fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        let value = visitor.visit_map(MapDeserializer::new(self))?;
-        if !self.peek_token(Delimiter::Map)? {
-            return Err(Error::ExpectedDelimiter(Delimiter::Map));
-        }
-        self.eat_token(Delimiter::Map)?;
-        Ok(value)
+        self.parse_map(visitor, false)
     }
 
     /// Tuple & Struct Deserialization.
@@ -494,7 +863,7 @@ impl<'de, 'a> Deserializer<'de> for &'a mut CustomDeserializer<'de> {
     where
         V: serde::de::Visitor<'de>,
     {
-        self.deserialize_seq(visitor)
+        self.parse_seq(visitor, false)
     }
     /// - struct: map()
     fn deserialize_struct<V>(
@@ -506,7 +875,7 @@ impl<'de, 'a> Deserializer<'de> for &'a mut CustomDeserializer<'de> {
     where
         V: serde::de::Visitor<'de>,
     {
-        self.deserialize_map(visitor)
+        self.parse_map(visitor, true)
     }
 
     fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value, Self::Error>
@@ -528,7 +897,7 @@ impl<'de, 'a> Deserializer<'de> for &'a mut CustomDeserializer<'de> {
 
 /// Handles the deserialization of an enum.
 /// enum() => variant_index + (depends on variant type; handled by VARIANT_ACCESS)
-impl<'de, 'a> EnumAccess<'de> for &'a mut CustomDeserializer<'de> {
+impl<'de> EnumAccess<'de> for &mut Deserializer<'de> {
     type Error = Error;
     type Variant = Self;
 
@@ -538,10 +907,11 @@ impl<'de, 'a> EnumAccess<'de> for &'a mut CustomDeserializer<'de> {
         V: serde::de::DeserializeSeed<'de>,
     {
         let key = self.parse_unsigned::<u32>()?;
-        Ok((seed.deserialize(key.into_deserializer())?, self))
+        let key = IntoDeserializer::<Error>::into_deserializer(key);
+        Ok((seed.deserialize(key)?, self))
     }
 }
-impl<'de, 'a> VariantAccess<'de> for &'a mut CustomDeserializer<'de> {
+impl<'de> VariantAccess<'de> for &mut Deserializer<'de> {
     type Error = Error;
 
     /// - unit_variant: variant_index
@@ -562,7 +932,7 @@ impl<'de, 'a> VariantAccess<'de> for &'a mut CustomDeserializer<'de> {
     where
         V: serde::de::Visitor<'de>,
     {
-        self.deserialize_seq(visitor)
+        self.parse_seq(visitor, false)
     }
 
     /// - struct_variant: variant_index + struct() where (struct() => map())
@@ -580,99 +950,181 @@ impl<'de, 'a> VariantAccess<'de> for &'a mut CustomDeserializer<'de> {
 
 /// Internal struct that handles the deserialization of a sequence.
 /// seq() => SEQ_DELIMITER + value_1 + SEQ_VALUE_DELIMITER + value_2 + SEQ_VALUE_DELIMITER + ... + SEQ_DELIMITER
+/// If the length is known (length-prefixed), there are no delimiters between the values.
 struct SequenceDeserializer<'a, 'de: 'a> {
-    deserializer: &'a mut CustomDeserializer<'de>,
-    first: bool,
+    deserializer: &'a mut Deserializer<'de>,
+    remaining: Option<usize>,
+    count: usize,
+    packed: Option<Kind>,
 }
 impl<'a, 'de> SequenceDeserializer<'a, 'de> {
-    pub fn new(deserializer: &'a mut CustomDeserializer<'de>) -> Self {
+    pub fn new(
+        deserializer: &'a mut Deserializer<'de>,
+        len: Option<usize>,
+        packed: Option<Kind>,
+    ) -> Self {
         Self {
             deserializer,
-            first: true,
+            remaining: len,
+            count: 0,
+            packed,
         }
     }
 }
-impl<'de, 'a> SeqAccess<'de> for SequenceDeserializer<'a, 'de> {
+impl<'de> SeqAccess<'de> for &mut SequenceDeserializer<'_, 'de> {
     type Error = Error;
 
     /// Grab the next element from the data and remove it.
+    /// - If the length is known; exit if there are no elements left, otherwise deserialize next element.
     /// - If at end of sequence; exit.
-    /// - If not first and not at the end of sequence; eat SEQ_VALUE_DELIMITER.
+    /// - If not first (or if the config is unambiguous) and not at the end of sequence; eat
+    ///   SEQ_VALUE_DELIMITER.
     /// - Make not first; deserialize next element.
+    /// - If the sequence is a packed run; read the next raw value instead.
     fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
     where
         T: serde::de::DeserializeSeed<'de>,
     {
-        // if at end of sequence; exit
-        if self.deserializer.peek_token(Delimiter::Seq)? {
-            return Ok(None);
-        }
-        // if not first and not at the end of sequence; eat SEQ_VALUE_DELIMITER
-        if !self.first {
-            if !self.deserializer.peek_token(Delimiter::SeqValue)? {
-                return Err(Error::ExpectedDelimiter(Delimiter::SeqValue));
+        match self.remaining {
+            // if no elements are left; exit
+            Some(0) => return Ok(None),
+            Some(ref mut remaining) => *remaining -= 1,
+            None => {
+                // if at end of sequence; exit
+                if self.deserializer.peek_token(Delimiter::Seq)? {
+                    return Ok(None);
+                }
+                // if not first (or unambiguous) and not at the end of sequence; eat SEQ_VALUE_DELIMITER
+                if self.count > 0 || self.deserializer.config.unambiguous {
+                    if !self.deserializer.peek_token(Delimiter::SeqValue)? {
+                        return Err(Error::ExpectedDelimiter(Delimiter::SeqValue));
+                    }
+                    self.deserializer.eat_token(Delimiter::SeqValue)?;
+                }
             }
-            self.deserializer.eat_token(Delimiter::SeqValue)?;
         }
         // make not first; deserialize next element
-        self.first = false;
+        self.count += 1;
+        if let Some(kind) = self.packed {
+            let value = Primitive::read(
+                kind,
+                &mut self.deserializer.data,
+                self.deserializer.config.endian,
+            )?;
+            return seed.deserialize(value).map(Some);
+        }
         seed.deserialize(&mut *self.deserializer).map(Some)
     }
+
+    /// The exact number of elements left if the sequence is length-prefixed.
+    fn size_hint(&self) -> Option<usize> {
+        self.remaining
+    }
 }
 
 /// Internal struct that handles the deserialization of a map.
 /// map() => key_1 + MAP_KEY_DELIMITER + value_1 + MAP_VALUE_DELIMITER + ... + MAP_DELIMITER
+/// If the length is known (length-prefixed), there are no delimiters between the keys and values.
+/// The keys of structs (`fields`) are interned field names if the config says so.
 struct MapDeserializer<'a, 'de: 'a> {
-    deserializer: &'a mut CustomDeserializer<'de>,
-    first: bool,
+    deserializer: &'a mut Deserializer<'de>,
+    remaining: Option<usize>,
+    count: usize,
+    fields: bool,
+    // the encoding of the previous key and its length in bits if the keys must be sorted.
+    previous: Option<(Vec<u8>, usize)>,
+    // reused buffer for the encoding of the current key.
+    key: Vec<u8>,
 }
 impl<'a, 'de> MapDeserializer<'a, 'de> {
-    pub fn new(deserializer: &'a mut CustomDeserializer<'de>) -> Self {
+    pub fn new(deserializer: &'a mut Deserializer<'de>, len: Option<usize>, fields: bool) -> Self {
         Self {
             deserializer,
-            first: true,
+            remaining: len,
+            count: 0,
+            fields,
+            previous: None,
+            key: Vec::new(),
         }
     }
+
+    /// Check that the key which was read from `start` on is greater than the previous key,
+    /// comparing their encodings like the serializer sorts them in canonical mode.
+    fn check_sorted(&mut self, start: usize) -> Result<(), Error> {
+        let end = self.deserializer.data.position();
+        self.deserializer.data.copy_bits(start, end, &mut self.key);
+        let key = (core::mem::take(&mut self.key), end - start);
+        if let Some(previous) = &self.previous {
+            match key.cmp(previous) {
+                core::cmp::Ordering::Less => return Err(Error::UnsortedKeys),
+                core::cmp::Ordering::Equal => return Err(Error::DuplicateKey),
+                core::cmp::Ordering::Greater => {}
+            }
+        }
+        if let Some((bytes, _)) = self.previous.replace(key) {
+            self.key = bytes;
+        }
+        Ok(())
+    }
 }
-impl<'de, 'a> MapAccess<'de> for MapDeserializer<'a, 'de> {
+impl<'de> MapAccess<'de> for &mut MapDeserializer<'_, 'de> {
     type Error = Error;
 
     /// Grab the next key from the data and remove it.
+    /// - If the length is known; exit if there are no entries left, otherwise deserialize next key.
     /// - If at end of map; exit.
-    /// - Make not first; deserialize next key_1.
-    /// - Deserialize next value.
+    /// - Deserialize next key (and check that it is sorted in canonical mode).
     /// - Eat MAP_KEY_DELIMITER.
     fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
     where
         K: serde::de::DeserializeSeed<'de>,
     {
-        // if at end of map; exit
-        if self.deserializer.peek_token(Delimiter::Map)? {
-            return Ok(None);
+        match self.remaining {
+            // if no entries are left; exit
+            Some(0) => return Ok(None),
+            Some(ref mut remaining) => *remaining -= 1,
+            // if at end of map; exit
+            None if self.deserializer.peek_token(Delimiter::Map)? => return Ok(None),
+            None => {}
         }
-        // make not first; deserialize next key_1
-        self.first = false;
-        let value = seed.deserialize(&mut *self.deserializer).map(Some)?;
-        if !self.deserializer.peek_token(Delimiter::MapKey)? {
-            return Err(Error::ExpectedDelimiter(Delimiter::MapKey));
+        self.count += 1;
+        let start = self.deserializer.data.position();
+        let value = match self.fields && self.deserializer.config.intern_field_names {
+            true => self.deserializer.parse_field_name(seed).map(Some)?,
+            false => seed.deserialize(&mut *self.deserializer).map(Some)?,
+        };
+        if !self.fields && self.deserializer.config.canonical {
+            self.check_sorted(start)?;
+        }
+        if self.remaining.is_none() {
+            if !self.deserializer.peek_token(Delimiter::MapKey)? {
+                return Err(Error::ExpectedDelimiter(Delimiter::MapKey));
+            }
+            self.deserializer.eat_token(Delimiter::MapKey)?;
         }
-        self.deserializer.eat_token(Delimiter::MapKey)?;
         Ok(value)
     }
 
     /// Grab the next value from the data and remove it.
     /// - Deserialize next value.
-    /// - Eat MAP_VALUE_DELIMITER.
+    /// - Eat MAP_VALUE_DELIMITER (if the length is not known).
     /// - Return value.
     fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::DeserializeSeed<'de>,
     {
         let value = seed.deserialize(&mut *self.deserializer)?;
-        if !self.deserializer.peek_token(Delimiter::MapValue)? {
-            return Err(Error::ExpectedDelimiter(Delimiter::MapValue));
+        if self.remaining.is_none() {
+            if !self.deserializer.peek_token(Delimiter::MapValue)? {
+                return Err(Error::ExpectedDelimiter(Delimiter::MapValue));
+            }
+            self.deserializer.eat_token(Delimiter::MapValue)?;
         }
-        self.deserializer.eat_token(Delimiter::MapValue)?;
         Ok(value)
     }
+
+    /// The exact number of entries left if the map is length-prefixed.
+    fn size_hint(&self) -> Option<usize> {
+        self.remaining
+    }
 }
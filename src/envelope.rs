@@ -0,0 +1,248 @@
+//! ### Envelope
+//! Internal module for the optional header written in front of the payload when
+//! [`Config::envelope`] is enabled:
+//! - magic bytes: `0xF5 R F R` (4 bytes)
+//! - format version: u8 (1 byte)
+//! - flags: u32, Little Endian (4 bytes); one bit per option the payload was written with.
+//!
+//! The deserializer recognizes the header by its magic bytes and takes the config from it,
+//! unless its config disables the envelope explicitly.
+//!
+//! It also handles the compression of [`Config::compression`] and the checksum of
+//! [`Config::checksum`] which is appended after the (zero padded, possibly compressed) payload
+//! and covers everything before it.
+
+use super::{
+    config::{BitOrder, Checksum, Compression, Config, Endian},
+    error::Error,
+};
+use alloc::{borrow::Cow, vec::Vec};
+
+/// The magic bytes at the start of every envelope.
+pub(crate) const MAGIC: [u8; 4] = *b"\xF5RFR";
+
+/// The format version written by this version of the crate.
+pub(crate) const VERSION: u8 = 1;
+
+/// The length of the header in bytes.
+pub(crate) const HEADER_LEN: usize = MAGIC.len() + 1 + 4;
+
+const LENGTH_PREFIXED: u32 = 1 << 0;
+const BYTE_ALIGNED: u32 = 1 << 1;
+const CHECKSUM_CRC32C: u32 = 1 << 2;
+const CHECKSUM_XXHASH64: u32 = 1 << 3;
+const COMPRESSION_LZ4: u32 = 1 << 4;
+const INTERN_FIELD_NAMES: u32 = 1 << 5;
+const PACKED_SEQUENCES: u32 = 1 << 6;
+const COMPACT_FLOATS: u32 = 1 << 7;
+const CANONICAL: u32 = 1 << 8;
+const BIG_ENDIAN: u32 = 1 << 9;
+const MSB0: u32 = 1 << 10;
+const UNAMBIGUOUS: u32 = 1 << 11;
+// the flags this build can decode.
+const KNOWN: u32 = LENGTH_PREFIXED
+    | BYTE_ALIGNED
+    | INTERN_FIELD_NAMES
+    | PACKED_SEQUENCES
+    | COMPACT_FLOATS
+    | CANONICAL
+    | BIG_ENDIAN
+    | MSB0
+    | UNAMBIGUOUS
+    | if cfg!(feature = "checksum") {
+        CHECKSUM_CRC32C | CHECKSUM_XXHASH64
+    } else {
+        0
+    }
+    | if cfg!(feature = "lz4") {
+        COMPRESSION_LZ4
+    } else {
+        0
+    };
+
+/// Get the flags of the options of the config.
+fn flags(config: Config) -> u32 {
+    let mut flags = 0;
+    if config.length_prefixed {
+        flags |= LENGTH_PREFIXED;
+    }
+    if config.byte_aligned {
+        flags |= BYTE_ALIGNED;
+    }
+    if config.intern_field_names {
+        flags |= INTERN_FIELD_NAMES;
+    }
+    if config.packed_sequences {
+        flags |= PACKED_SEQUENCES;
+    }
+    if config.compact_floats {
+        flags |= COMPACT_FLOATS;
+    }
+    if config.canonical {
+        flags |= CANONICAL;
+    }
+    if config.endian == Endian::Big {
+        flags |= BIG_ENDIAN;
+    }
+    if config.bit_order == BitOrder::Msb0 {
+        flags |= MSB0;
+    }
+    if config.unambiguous {
+        flags |= UNAMBIGUOUS;
+    }
+    flags |= match config.checksum {
+        Checksum::None => 0,
+        #[cfg(feature = "checksum")]
+        Checksum::Crc32c => CHECKSUM_CRC32C,
+        #[cfg(feature = "checksum")]
+        Checksum::XxHash64 => CHECKSUM_XXHASH64,
+    };
+    flags |= match config.compression {
+        Compression::None => 0,
+        #[cfg(feature = "lz4")]
+        Compression::Lz4 => COMPRESSION_LZ4,
+    };
+    flags
+}
+
+/// Get the config described by the flags.
+fn config(flags: u32) -> Result<Config, Error> {
+    let checksum = match flags & (CHECKSUM_CRC32C | CHECKSUM_XXHASH64) {
+        0 => Checksum::None,
+        #[cfg(feature = "checksum")]
+        CHECKSUM_CRC32C => Checksum::Crc32c,
+        #[cfg(feature = "checksum")]
+        CHECKSUM_XXHASH64 => Checksum::XxHash64,
+        _ => return Err(Error::UnsupportedFlags(flags)),
+    };
+    if flags & !KNOWN != 0 {
+        return Err(Error::UnsupportedFlags(flags));
+    }
+    #[cfg(feature = "lz4")]
+    let compression = match flags & COMPRESSION_LZ4 {
+        0 => Compression::None,
+        _ => Compression::Lz4,
+    };
+    #[cfg(not(feature = "lz4"))]
+    let compression = Compression::None;
+    Ok(Config::new()
+        .envelope(true)
+        .length_prefixed(flags & LENGTH_PREFIXED != 0)
+        .byte_aligned(flags & BYTE_ALIGNED != 0)
+        .intern_field_names(flags & INTERN_FIELD_NAMES != 0)
+        .packed_sequences(flags & PACKED_SEQUENCES != 0)
+        .compact_floats(flags & COMPACT_FLOATS != 0)
+        .canonical(flags & CANONICAL != 0)
+        .endian(match flags & BIG_ENDIAN {
+            0 => Endian::Little,
+            _ => Endian::Big,
+        })
+        .bit_order(match flags & MSB0 {
+            0 => BitOrder::Lsb0,
+            _ => BitOrder::Msb0,
+        })
+        .unambiguous(flags & UNAMBIGUOUS != 0)
+        .checksum(checksum)
+        .compression(compression))
+}
+
+/// Get the header for the config, if the config uses an envelope.
+pub(crate) fn header(config: Config) -> Option<[u8; HEADER_LEN]> {
+    if config.envelope != Some(true) {
+        return None;
+    }
+    let mut header = [0u8; HEADER_LEN];
+    header[..4].copy_from_slice(&MAGIC);
+    header[4] = VERSION;
+    header[5..].copy_from_slice(&flags(config).to_le_bytes());
+    Some(header)
+}
+
+/// Get the checksum of the serialized bytes as the bytes to append to them. Empty if the
+/// config doesn't use a checksum.
+pub(crate) fn trailer(config: Config, bytes: &[u8]) -> ([u8; 8], usize) {
+    let checksum = config.checksum.compute(bytes);
+    (checksum.to_le_bytes(), config.checksum.size())
+}
+
+/// Finish the message which starts at `start` in the bytes (with the header, if any, and the
+/// zero padded payload): compress the payload and append the checksum.
+pub(crate) fn seal(config: Config, bytes: &mut Vec<u8>, start: usize) {
+    #[cfg(feature = "lz4")]
+    if config.compression == Compression::Lz4 {
+        let payload = start + header(config).map_or(0, |header| header.len());
+        let compressed = lz4_flex::compress_prepend_size(&bytes[payload..]);
+        bytes.truncate(payload);
+        bytes.extend_from_slice(&compressed);
+    }
+    let (trailer, len) = trailer(config, &bytes[start..]);
+    bytes.extend_from_slice(&trailer[..len]);
+}
+
+/// Split the header and the checksum off the bytes, verify the checksum, decompress the
+/// payload and get the config the header describes along with the payload. Bytes without the
+/// magic bytes keep the given config, unless the config expects an envelope, and so do all
+/// bytes if the config disables the envelope.
+pub(crate) fn open(bytes: &[u8], config: Config) -> Result<(Config, Cow<'_, [u8]>), Error> {
+    let (config, payload) = verify(bytes, config)?;
+    match config.compression {
+        Compression::None => Ok((config, Cow::Borrowed(payload))),
+        #[cfg(feature = "lz4")]
+        Compression::Lz4 => {
+            let payload = lz4_flex::decompress_size_prepended(payload)
+                .map_err(|e| Error::DecompressionError(alloc::string::ToString::to_string(&e)))?;
+            Ok((config, Cow::Owned(payload)))
+        }
+    }
+}
+
+/// Split the header and the checksum off the bytes and verify the checksum.
+fn verify(bytes: &[u8], config: Config) -> Result<(Config, &[u8]), Error> {
+    let (config, header_len) = parse_header(bytes, config)?;
+    let checksum_len = config.checksum.size();
+    if checksum_len == 0 {
+        return Ok((config, &bytes[header_len..]));
+    }
+    let len = bytes
+        .len()
+        .checked_sub(checksum_len)
+        .filter(|len| *len >= header_len)
+        .ok_or(Error::UnexpectedEOF)?;
+    let mut expected = [0u8; 8];
+    expected[..checksum_len].copy_from_slice(&bytes[len..]);
+    let expected = u64::from_le_bytes(expected);
+    let actual = config.checksum.compute(&bytes[..len]);
+    if expected != actual {
+        return Err(Error::ChecksumMismatch { expected, actual });
+    }
+    Ok((config, &bytes[header_len..len]))
+}
+
+/// Parse the header at the start of the bytes and get the config it describes along with the
+/// length of the header. A config with the envelope disabled explicitly never has a header.
+fn parse_header(bytes: &[u8], config: Config) -> Result<(Config, usize), Error> {
+    if config.envelope == Some(false) {
+        return Ok((config, 0));
+    }
+    if !bytes.starts_with(&MAGIC) {
+        return match config.envelope {
+            Some(true) => Err(Error::MissingEnvelope),
+            _ => Ok((config, 0)),
+        };
+    }
+    if bytes.len() < HEADER_LEN {
+        return Err(Error::UnexpectedEOF);
+    }
+    let version = bytes[4];
+    if version != VERSION {
+        return Err(Error::UnsupportedVersion(version));
+    }
+    let mut flags = [0u8; 4];
+    flags.copy_from_slice(&bytes[5..HEADER_LEN]);
+    let header = self::config(u32::from_le_bytes(flags))?;
+    // a corrupted header must not be able to turn off the checksum the caller asked for.
+    if header.checksum == Checksum::None && config.checksum != Checksum::None {
+        return Err(Error::MissingChecksum);
+    }
+    Ok((header, HEADER_LEN))
+}
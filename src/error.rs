@@ -3,6 +3,7 @@
 //! error that can occur in the library. It uses `thiserror` internally.
 
 use super::serializer::Delimiter;
+use alloc::string::{String, ToString};
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -35,12 +36,67 @@ pub enum Error {
 
     #[error("expected delimiter {0}")]
     ExpectedDelimiter(Delimiter),
+
+    #[error("expected {0} elements but found {1}")]
+    LengthMismatch(usize, usize),
+
+    #[error("invalid varint")]
+    InvalidVarint,
+
+    #[error("the output buffer is too small")]
+    BufferTooSmall,
+
+    #[error("{0} bits of trailing data")]
+    TrailingData(usize),
+
+    #[error("missing envelope header")]
+    MissingEnvelope,
+
+    #[error("unsupported format version {0}")]
+    UnsupportedVersion(u8),
+
+    #[error("unsupported envelope flags {0:#x}")]
+    UnsupportedFlags(u32),
+
+    #[error("checksum mismatch: expected {expected:#x} but found {actual:#x}")]
+    ChecksumMismatch { expected: u64, actual: u64 },
+
+    #[error("missing checksum")]
+    MissingChecksum,
+
+    #[error("could not decompress the payload: {0}")]
+    DecompressionError(String),
+
+    #[error("unknown field name index {0}")]
+    UnknownFieldName(u64),
+
+    #[error("invalid packed sequence kind {0}")]
+    InvalidPackedKind(u8),
+
+    #[error("NaN is not allowed by the config")]
+    NanNotAllowed,
+
+    #[error("duplicate map key")]
+    DuplicateKey,
+
+    #[error("map keys are not sorted")]
+    UnsortedKeys,
+
+    #[error("incompatible options: {0}")]
+    IncompatibleConfig(&'static str),
+
+    #[error("frame of {0} bytes exceeds the maximum of {1} bytes")]
+    FrameTooLarge(usize, usize),
+
+    #[cfg(feature = "std")]
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
 }
 
 impl serde::ser::Error for Error {
     fn custom<T>(msg: T) -> Self
     where
-        T: std::fmt::Display,
+        T: core::fmt::Display,
     {
         Error::SerializationError(msg.to_string())
     }
@@ -49,7 +105,7 @@ impl serde::ser::Error for Error {
 impl serde::de::Error for Error {
     fn custom<T>(msg: T) -> Self
     where
-        T: std::fmt::Display,
+        T: core::fmt::Display,
     {
         Error::DeserializationError(msg.to_string())
     }
@@ -0,0 +1,256 @@
+//! ### Float
+//! Internal module for the compact floats of [`Config::compact_floats`], the canonicalization
+//! of [`Config::nan_policy`] and [`Config::normalize_negative_zero`] and the `f16` wrapper
+//! type for half-precision values (`f16` feature).
+
+use super::{
+    config::{Config, NanPolicy},
+    error::Error,
+};
+#[cfg(feature = "f16")]
+use core::fmt;
+#[cfg(feature = "f16")]
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// The name of the newtype struct `f16` is wrapped in, so that rust-fr can recognize it.
+pub(crate) const F16_TOKEN: &str = "$rust_fr::private::f16";
+
+/// The number of bits the tag of a compact float takes (unless the config is byte-aligned).
+pub(crate) const TAG_BITS: usize = 2;
+
+/// The shortest lossless representation of a float in compact mode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Compact {
+    /// An integer, zigzag encoded (tag 0).
+    Int(u64),
+    /// Half precision, as its bits (tag 1).
+    F16(u16),
+    /// Single precision (tag 2).
+    F32(f32),
+    /// Double precision (tag 3).
+    F64(f64),
+}
+
+impl Compact {
+    /// Get the shortest lossless representation of the value. `wide` tells whether the value
+    /// is an f64; an f32 never needs more than 32 bits.
+    pub(crate) fn of(value: f64, wide: bool) -> Self {
+        let mut compact = match wide {
+            true => (Compact::F64(value), 64),
+            false => (Compact::F32(value as f32), 32),
+        };
+        let narrow = value as f32;
+        if wide && f64::from(narrow).to_bits() == value.to_bits() {
+            compact = (Compact::F32(narrow), 32);
+        }
+        if let Some(half) = exact_f16_bits(value) {
+            compact = (Compact::F16(half), 16);
+        }
+        let int = value as i64;
+        if (int as f64).to_bits() == value.to_bits() {
+            let zigzag = ((int << 1) ^ (int >> 63)) as u64;
+            let bits = usize::max(1, (64 - zigzag.leading_zeros() as usize).div_ceil(7)) * 8;
+            if bits <= compact.1 {
+                compact = (Compact::Int(zigzag), bits);
+            }
+        }
+        compact.0
+    }
+
+    /// Get the tag of the representation.
+    pub(crate) fn tag(self) -> u64 {
+        match self {
+            Compact::Int(_) => 0,
+            Compact::F16(_) => 1,
+            Compact::F32(_) => 2,
+            Compact::F64(_) => 3,
+        }
+    }
+}
+
+/// Apply the NaN policy and the -0.0 normalization of the config to an f32.
+pub(crate) fn canonical_f32(value: f32, config: Config) -> Result<f32, Error> {
+    if value.is_nan() {
+        return match config.nan_policy {
+            NanPolicy::Preserve => Ok(value),
+            NanPolicy::Canonicalize => Ok(f32::from_bits(0x7fc0_0000)),
+            NanPolicy::Reject => Err(Error::NanNotAllowed),
+        };
+    }
+    if value == 0.0 && config.normalize_negative_zero {
+        return Ok(0.0);
+    }
+    Ok(value)
+}
+
+/// Apply the NaN policy and the -0.0 normalization of the config to an f64.
+pub(crate) fn canonical_f64(value: f64, config: Config) -> Result<f64, Error> {
+    if value.is_nan() {
+        return match config.nan_policy {
+            NanPolicy::Preserve => Ok(value),
+            NanPolicy::Canonicalize => Ok(f64::from_bits(0x7ff8_0000_0000_0000)),
+            NanPolicy::Reject => Err(Error::NanNotAllowed),
+        };
+    }
+    if value == 0.0 && config.normalize_negative_zero {
+        return Ok(0.0);
+    }
+    Ok(value)
+}
+
+/// Get the value of a zigzag encoded integer of a compact float.
+pub(crate) fn unzigzag(zigzag: u64) -> f64 {
+    ((zigzag >> 1) as i64 ^ -((zigzag & 1) as i64)) as f64
+}
+
+/// Get the value of the bits of a half-precision float. NaNs are quieted, as `half` does.
+pub(crate) fn from_f16_bits(bits: u16) -> f64 {
+    let sign = u64::from(bits >> 15) << 63;
+    let exponent = u64::from((bits >> 10) & 0x1f);
+    let mantissa = u64::from(bits & 0x3ff);
+    let magnitude = match exponent {
+        // zero and subnormals: mantissa * 2^-24
+        0 => (mantissa as f64 / 16_777_216.0).to_bits(),
+        0x1f if mantissa == 0 => 0x7ff0_0000_0000_0000,
+        0x1f => 0x7ff8_0000_0000_0000 | (mantissa << 42),
+        _ => ((exponent + 1023 - 15) << 52) | (mantissa << 42),
+    };
+    f64::from_bits(sign | magnitude)
+}
+
+/// Get the bits of the value as a half-precision float if it converts back to the same bits.
+pub(crate) fn exact_f16_bits(value: f64) -> Option<u16> {
+    let bits = value.to_bits();
+    let sign = ((bits >> 48) & 0x8000) as u16;
+    let exponent = ((bits >> 52) & 0x7ff) as i64 - 1023;
+    let mantissa = bits & 0x000f_ffff_ffff_ffff;
+    let half = match exponent {
+        // zero (f64 subnormals are too small)
+        -1023 => sign,
+        // infinity and NaN
+        1024 => sign | 0x7c00 | (mantissa >> 42) as u16,
+        -14..=15 => sign | (((exponent + 15) as u16) << 10) | (mantissa >> 42) as u16,
+        // subnormal: (2^52 + mantissa) * 2^(exponent - 52) = half * 2^-24
+        -24..=-15 => sign | (((1 << 52) | mantissa) >> (28 - exponent)) as u16,
+        _ => return None,
+    };
+    (from_f16_bits(half).to_bits() == bits).then_some(half)
+}
+
+/// Get the bits of the value rounded to half precision.
+#[cfg(feature = "f16")]
+pub(crate) fn to_f16_bits(value: f32) -> u16 {
+    half::f16::from_f32(value).to_bits()
+}
+
+/// A half-precision (16 bit) float. rust-fr writes it as 2 bytes (in the byte order of the
+/// config) whatever the other options are; other formats get it as an f32. Requires the `f16`
+/// feature.
+///
+/// ```rust
+/// use rust_fr::{deserializer, f16, serializer};
+///
+/// let value = f16::from_f32(0.5);
+/// let bytes = serializer::to_bytes(&value).unwrap();
+/// assert_eq!(bytes.len(), 2);
+/// assert_eq!(deserializer::from_bytes::<f16>(&bytes).unwrap().to_f32(), 0.5);
+/// ```
+#[cfg(feature = "f16")]
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Default)]
+pub struct f16(half::f16);
+
+#[cfg(feature = "f16")]
+impl f16 {
+    /// Round the value to the nearest half-precision float.
+    pub fn from_f32(value: f32) -> Self {
+        Self(half::f16::from_f32(value))
+    }
+
+    /// Round the value to the nearest half-precision float.
+    pub fn from_f64(value: f64) -> Self {
+        Self(half::f16::from_f64(value))
+    }
+
+    /// Get the value as an f32 (lossless).
+    pub fn to_f32(self) -> f32 {
+        self.0.to_f32()
+    }
+
+    /// Get the value as an f64 (lossless).
+    pub fn to_f64(self) -> f64 {
+        self.0.to_f64()
+    }
+
+    /// Create the value from its IEEE 754 binary16 bits.
+    pub const fn from_bits(bits: u16) -> Self {
+        Self(half::f16::from_bits(bits))
+    }
+
+    /// Get the IEEE 754 binary16 bits of the value.
+    pub const fn to_bits(self) -> u16 {
+        self.0.to_bits()
+    }
+}
+
+#[cfg(feature = "f16")]
+impl From<f16> for f32 {
+    fn from(value: f16) -> Self {
+        value.to_f32()
+    }
+}
+
+#[cfg(feature = "f16")]
+impl From<f16> for f64 {
+    fn from(value: f16) -> Self {
+        value.to_f64()
+    }
+}
+
+#[cfg(feature = "f16")]
+impl fmt::Display for f16 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+#[cfg(feature = "f16")]
+impl Serialize for f16 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(F16_TOKEN, &self.to_f32())
+    }
+}
+
+#[cfg(feature = "f16")]
+impl<'de> Deserialize<'de> for f16 {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_newtype_struct(F16_TOKEN, F16Visitor)
+    }
+}
+
+#[cfg(feature = "f16")]
+struct F16Visitor;
+
+#[cfg(feature = "f16")]
+impl<'de> de::Visitor<'de> for F16Visitor {
+    type Value = f16;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a half-precision float")
+    }
+
+    fn visit_f32<E: de::Error>(self, v: f32) -> Result<Self::Value, E> {
+        Ok(f16::from_f32(v))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+        Ok(f16::from_f64(v))
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        f32::deserialize(deserializer).map(f16::from_f32)
+    }
+}
@@ -0,0 +1,200 @@
+//! ### Framing
+//! A module to write several messages to one stream (a socket, a file...) and read them back.
+//! A message can't tell where it ends within a stream of bytes (its last byte is zero padded),
+//! so every message is written as a frame: its length in bytes as a varint (LEB128) followed
+//! by the message itself. Requires the `std` feature.
+//!
+//! ```rust
+//! use rust_fr::framing::{self, FrameReader};
+//!
+//! let mut stream = Vec::new();
+//! framing::write_frame(&(1u8, "first"), &mut stream).unwrap();
+//! framing::write_frame(&(2u8, "second"), &mut stream).unwrap();
+//!
+//! let frames = FrameReader::<_, (u8, String)>::new(&stream[..]);
+//! let values = frames.collect::<Result<Vec<_>, _>>().unwrap();
+//! assert_eq!(values, [(1, "first".to_string()), (2, "second".to_string())]);
+//! ```
+
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    io::{self, Read, Write},
+    marker::PhantomData,
+};
+
+use super::{config::Config, deserializer, error::Error, serializer};
+
+/// The largest frame a reader accepts unless told otherwise (8 MiB).
+pub const DEFAULT_MAX_FRAME_LENGTH: usize = 8 * 1024 * 1024;
+
+/// The most bytes the varint length of a frame takes.
+pub(crate) const MAX_LENGTH_BYTES: usize = 10;
+
+/// Encode the length of a frame as a varint. The first returned number of bytes are used.
+pub(crate) fn encode_length(mut len: usize) -> ([u8; MAX_LENGTH_BYTES], usize) {
+    let mut bytes = [0u8; MAX_LENGTH_BYTES];
+    let mut n = 0;
+    loop {
+        let byte = (len & 0x7f) as u8;
+        len >>= 7;
+        if len == 0 {
+            bytes[n] = byte;
+            return (bytes, n + 1);
+        }
+        bytes[n] = byte | 0x80;
+        n += 1;
+    }
+}
+
+/// Decode the varint length of a frame from the start of the bytes. Returns the length and
+/// the number of bytes it took, or `None` if the bytes end before the varint does.
+pub(crate) fn decode_length(bytes: &[u8]) -> Result<Option<(usize, usize)>, Error> {
+    let mut len = 0u64;
+    for (i, byte) in bytes.iter().take(MAX_LENGTH_BYTES).enumerate() {
+        // the last byte holds the 64th bit only and ends the varint.
+        if i == MAX_LENGTH_BYTES - 1 && *byte > 1 {
+            return Err(Error::InvalidVarint);
+        }
+        len |= u64::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            let len = usize::try_from(len).map_err(|_| Error::InvalidVarint)?;
+            return Ok(Some((len, i + 1)));
+        }
+    }
+    if bytes.len() >= MAX_LENGTH_BYTES {
+        return Err(Error::InvalidVarint);
+    }
+    Ok(None)
+}
+
+/// Serialize the `value` and write it to the writer as a frame. The writer is not flushed.
+pub fn write_frame<W, T>(value: &T, writer: W) -> Result<(), Error>
+where
+    W: Write,
+    T: Serialize,
+{
+    write_frame_with_config(value, writer, Config::default())
+}
+
+/// The same as [`write_frame`] but the encoding is tweaked by the given [`Config`].
+pub fn write_frame_with_config<W, T>(value: &T, mut writer: W, config: Config) -> Result<(), Error>
+where
+    W: Write,
+    T: Serialize,
+{
+    let bytes = serializer::to_bytes_with_config(value, config)?;
+    let (length, n) = encode_length(bytes.len());
+    writer.write_all(&length[..n])?;
+    writer.write_all(&bytes)?;
+    Ok(())
+}
+
+/// Read the next frame from the reader and deserialize it. Returns `None` if the reader ends
+/// right before a frame. Frames longer than [`DEFAULT_MAX_FRAME_LENGTH`] are rejected; see
+/// [`FrameReader`] to read many frames or to choose the maximum.
+pub fn read_frame<R, T>(reader: R) -> Result<Option<T>, Error>
+where
+    R: Read,
+    T: DeserializeOwned,
+{
+    read_frame_with_config(reader, Config::default())
+}
+
+/// The same as [`read_frame`] but for frames that were serialized with the given [`Config`].
+pub fn read_frame_with_config<R, T>(reader: R, config: Config) -> Result<Option<T>, Error>
+where
+    R: Read,
+    T: DeserializeOwned,
+{
+    FrameReader::with_config(reader, config).read_frame()
+}
+
+/// Reads frames of values of type `T` from a reader, reusing one buffer for all of them. It
+/// is also an iterator over the values, which ends when the reader ends right before a frame.
+/// After an error, the position of the reader within the stream is unspecified.
+#[derive(Debug)]
+pub struct FrameReader<R, T> {
+    reader: R,
+    config: Config,
+    max_frame_length: usize,
+    buffer: Vec<u8>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<R: Read, T: DeserializeOwned> FrameReader<R, T> {
+    /// Create a frame reader for messages serialized with the default config.
+    pub fn new(reader: R) -> Self {
+        Self::with_config(reader, Config::default())
+    }
+
+    /// Create a frame reader for messages serialized with the given config.
+    pub fn with_config(reader: R, config: Config) -> Self {
+        Self {
+            reader,
+            config,
+            max_frame_length: DEFAULT_MAX_FRAME_LENGTH,
+            buffer: Vec::new(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Set the length (in bytes, without the length itself) of the largest frame the reader
+    /// accepts. Longer frames are rejected with [`Error::FrameTooLarge`] before anything is
+    /// allocated for them. Defaults to [`DEFAULT_MAX_FRAME_LENGTH`].
+    pub fn max_frame_length(mut self, max_frame_length: usize) -> Self {
+        self.max_frame_length = max_frame_length;
+        self
+    }
+
+    /// Read the next frame and deserialize it. Returns `None` if the reader ends right before
+    /// a frame.
+    pub fn read_frame(&mut self) -> Result<Option<T>, Error> {
+        let Some(len) = self.read_length()? else {
+            return Ok(None);
+        };
+        if len > self.max_frame_length {
+            return Err(Error::FrameTooLarge(len, self.max_frame_length));
+        }
+        self.buffer.clear();
+        self.buffer.resize(len, 0);
+        self.reader.read_exact(&mut self.buffer).map_err(eof)?;
+        deserializer::from_bytes_with_config(&self.buffer, self.config).map(Some)
+    }
+
+    /// Get the reader back.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Read the varint length of the next frame, a byte at a time so that nothing past it is
+    /// consumed. Returns `None` if the reader ends before the first byte.
+    fn read_length(&mut self) -> Result<Option<usize>, Error> {
+        let mut bytes = [0u8; MAX_LENGTH_BYTES];
+        for i in 0..MAX_LENGTH_BYTES {
+            match self.reader.read_exact(&mut bytes[i..=i]) {
+                Err(e) if i == 0 && e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                result => result.map_err(eof)?,
+            }
+            if let Some((len, _)) = decode_length(&bytes[..=i])? {
+                return Ok(Some(len));
+            }
+        }
+        Err(Error::InvalidVarint)
+    }
+}
+
+impl<R: Read, T: DeserializeOwned> Iterator for FrameReader<R, T> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_frame().transpose()
+    }
+}
+
+/// Report a reader that ends within a frame as [`Error::UnexpectedEOF`].
+fn eof(error: io::Error) -> Error {
+    match error.kind() {
+        io::ErrorKind::UnexpectedEof => Error::UnexpectedEOF,
+        _ => Error::Io(error),
+    }
+}
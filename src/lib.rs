@@ -3,6 +3,10 @@
 //! and `deserializer`, for serializing and deserializing data which contain [`to_bytes`](serializer::to_bytes),
 //! [`from_bytes`](deserializer::from_bytes) functions which do exactly what their names suggest.
 //! - The data to be encoded & decoded must implement the `serde::Serialize` and `serde::Deserialize` traits.
+//! - [`Serializer`] and [`Deserializer`] can be used directly to compose with other serde tools.
+//! - [`hash`] and [`hash_into`] hash the canonical encoding of a value without allocating it.
+//! - [`framing`] writes and reads streams of several messages, each prefixed with its length.
+//! - [`delta`] encodes mostly monotonic integer sequences compactly with `#[serde(with = "rust_fr::delta")]`.
 //!
 //! ### Example
 //! ```rust
@@ -27,16 +31,55 @@
 //!
 //! assert_eq!(human, deserialized_human);
 //! ```
+//!
+//! ### Features
+//! - `std` (default): `std::io` support, i.e. [`from_reader`](deserializer::from_reader),
+//!   the [`framing`] module and [`Error::Io`](error::Error::Io). Without it the crate is
+//!   `no_std` and only needs `alloc`; [`to_slice`](serializer::to_slice) doesn't allocate at
+//!   all unless the config needs a buffer (compression, canonical maps or interned field names).
+//! - `checksum`: CRC-32C and xxHash64 checksums ([`Checksum`](config::Checksum)).
+//! - `f16`: the `f16` half-precision float type.
+//! - `lz4`: LZ4 compression of the payload ([`Compression::Lz4`](config::Compression)).
+//! - `async`: tokio support, i.e. `serializer::to_async_writer`,
+//!   `deserializer::from_async_reader` and `codec::RustFrCodec`, a `tokio_util` codec that
+//!   frames messages.
+//! - `futures-io`: the same for the `futures` IO traits, i.e.
+//!   `serializer::to_futures_writer` and `deserializer::from_futures_reader`.
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
 
+mod bits;
+#[cfg(feature = "async")]
+pub mod codec;
+pub mod config;
+pub mod delta;
 pub mod deserializer;
+mod envelope;
 pub mod error;
+mod float;
+#[cfg(feature = "std")]
+pub mod framing;
+mod packed;
 pub mod serializer;
 
+pub use deserializer::Deserializer;
+#[cfg(feature = "f16")]
+pub use float::f16;
+pub use serializer::{hash, hash_into, Serializer};
+
 #[cfg(test)]
 mod tests {
-    use crate::{deserializer, serializer};
+    use crate::{
+        bits::{BitReader, BitWriter},
+        config::{BitOrder, Config, Endian, NanPolicy},
+        deserializer,
+        error::Error,
+        serializer,
+    };
     use serde::{Deserialize, Serialize};
-    use std::collections::HashMap;
+    use std::{borrow::Cow, collections::HashMap};
 
     #[derive(Debug, Serialize, Deserialize, PartialEq)]
     struct Primitives {
@@ -81,6 +124,16 @@ mod tests {
         assert_eq!(primitives, deserialized_primitives);
     }
 
+    #[test]
+    fn invalid_char() {
+        // a surrogate is not a char.
+        let surrogate = serializer::to_bytes(&0xD800u32).unwrap();
+        assert!(matches!(
+            deserializer::from_bytes::<char>(&surrogate),
+            Err(Error::ConversionError)
+        ));
+    }
+
     #[derive(Debug, Serialize, Deserialize, PartialEq)]
     struct CompundTypes {
         a: Vec<u8>,
@@ -212,6 +265,1553 @@ mod tests {
         assert_eq!(human, deserialized_human);
     }
 
+    #[test]
+    fn sequence_value_delimiters() {
+        // 100 = 0b01100100; its last 3 bits look like SEQ_DELIMITER.
+        let values = vec![100u8, 1, 2];
+
+        let bytes = serializer::to_bytes(&values).unwrap();
+        let deserialized_values = deserializer::from_bytes::<Vec<u8>>(&bytes).unwrap();
+        assert_eq!(values, deserialized_values);
+    }
+
+    #[test]
+    fn length_prefixed_nested_sequences() {
+        let config = Config::new().length_prefixed(true);
+        let nested = vec![vec![1u8], vec![2, 3], vec![]];
+
+        let bytes = serializer::to_bytes_with_config(&nested, config).unwrap();
+        let deserialized_nested =
+            deserializer::from_bytes_with_config::<Vec<Vec<u8>>>(&bytes, config).unwrap();
+        assert_eq!(nested, deserialized_nested);
+    }
+
+    #[test]
+    fn length_prefixed() {
+        let config = Config::new().length_prefixed(true);
+        let compound_types = CompundTypes {
+            a: vec![1, 2, 3],
+            b: [("a".to_string(), 1), ("b".to_string(), 2)]
+                .iter()
+                .cloned()
+                .collect(),
+            c: Some(1),
+            d: None,
+            e: Primitives {
+                a: 1,
+                b: 2,
+                c: 3,
+                d: 4,
+                e: -1,
+                f: -2,
+                g: -3,
+                h: -4,
+                i: 1.0,
+                j: 2.0,
+                k: true,
+                l: 'a',
+                m: "hello".to_string(),
+            },
+            f: vec![AnEnum::A(1), AnEnum::B { a: 1, b: 2 }, AnEnum::C],
+        };
+
+        let bytes = serializer::to_bytes_with_config(&compound_types, config).unwrap();
+        let deserialized_compound_types =
+            deserializer::from_bytes_with_config::<CompundTypes>(&bytes, config).unwrap();
+        assert_eq!(compound_types, deserialized_compound_types);
+
+        // a sequence of unknown length falls back to delimiters.
+        struct Evens;
+        impl Serialize for Evens {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_seq((0..10u8).filter(|i| i % 2 == 0))
+            }
+        }
+        let bytes = serializer::to_bytes_with_config(&Evens, config).unwrap();
+        let evens = deserializer::from_bytes_with_config::<Vec<u8>>(&bytes, config).unwrap();
+        assert_eq!(evens, vec![0, 2, 4, 6, 8]);
+    }
+
+    #[test]
+    fn length_prefixed_size_hint() {
+        struct SizeHints(Vec<Option<usize>>);
+        impl<'de> Deserialize<'de> for SizeHints {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                struct SizeHintVisitor;
+                impl<'de> serde::de::Visitor<'de> for SizeHintVisitor {
+                    type Value = SizeHints;
+                    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                        f.write_str("a sequence")
+                    }
+                    fn visit_seq<A>(self, mut seq: A) -> Result<SizeHints, A::Error>
+                    where
+                        A: serde::de::SeqAccess<'de>,
+                    {
+                        let mut hints = vec![seq.size_hint()];
+                        while seq.next_element::<u16>()?.is_some() {
+                            hints.push(seq.size_hint());
+                        }
+                        Ok(SizeHints(hints))
+                    }
+                }
+                deserializer.deserialize_seq(SizeHintVisitor)
+            }
+        }
+
+        let values: Vec<u16> = vec![1, 2, 3];
+        let config = Config::new().length_prefixed(true);
+        let bytes = serializer::to_bytes_with_config(&values, config).unwrap();
+        let hints = deserializer::from_bytes_with_config::<SizeHints>(&bytes, config).unwrap();
+        assert_eq!(hints.0, vec![Some(3), Some(2), Some(1), Some(0)]);
+
+        let bytes = serializer::to_bytes(&values).unwrap();
+        let hints = deserializer::from_bytes::<SizeHints>(&bytes).unwrap();
+        assert_eq!(hints.0, vec![None; 4]);
+    }
+
+    #[test]
+    fn unambiguous() {
+        // values whose first bits read as the Seq (011) or Unit (010) delimiter.
+        fn check<T>(value: T)
+        where
+            T: Serialize + for<'de> Deserialize<'de> + PartialEq + std::fmt::Debug,
+        {
+            for config in [
+                Config::new(),
+                Config::new().byte_aligned(true),
+                Config::new().bit_order(BitOrder::Msb0),
+                Config::new().length_prefixed(true),
+                Config::new().packed_sequences(true),
+            ] {
+                let config = config.unambiguous(true);
+                let bytes = serializer::to_bytes_with_config(&value, config).unwrap();
+                let decoded: T = deserializer::from_bytes_with_config(&bytes, config).unwrap();
+                assert_eq!(decoded, value);
+            }
+        }
+
+        check(vec![3u8, 1]);
+        check(vec![vec![1u8]]);
+        check(vec![vec![vec![3u8], vec![]], vec![vec![2, 3]]]);
+        check((vec![1u16], 7u8));
+        check(Some(2u8));
+        check(vec![Some(3u8), None, Some(2)]);
+        check(vec![(), ()]);
+
+        // every element gets a SeqValue delimiter and options a bool.
+        let config = Config::new().byte_aligned(true).unambiguous(true);
+        let bytes = serializer::to_bytes_with_config(&(true, 1u16), config).unwrap();
+        assert_eq!(bytes, vec![3, 4, 1, 4, 1, 0, 3]);
+        let bytes = serializer::to_bytes_with_config(&Some(2u8), config).unwrap();
+        assert_eq!(bytes, vec![1, 2]);
+
+        // the envelope records the option.
+        let config = Config::new().envelope(true).unambiguous(true);
+        let bytes = serializer::to_bytes_with_config(&vec![3u8, 1], config).unwrap();
+        let envelope = Config::new().envelope(true);
+        let decoded = deserializer::from_bytes_with_config::<Vec<u8>>(&bytes, envelope);
+        assert_eq!(decoded.unwrap(), vec![3, 1]);
+    }
+
+    #[test]
+    fn byte_aligned() {
+        let config = Config::new().byte_aligned(true);
+
+        // every token and every bool takes a whole byte.
+        let bytes = serializer::to_bytes_with_config(&(true, 1u16), config).unwrap();
+        assert_eq!(bytes, vec![3, 1, 4, 1, 0, 3]);
+
+        let random = Random {
+            a: 1,
+            b: 2,
+            c: 3,
+            d: 4,
+            e: -1,
+            f: -2,
+            g: -3,
+            h: -4,
+            i: 1.0,
+            j: 2.0,
+            k: true,
+            l: 'a',
+            m: "hello".to_string(),
+            n: vec![1, 2, 3],
+            o: [("a".to_string(), 1), ("b".to_string(), 2)]
+                .iter()
+                .cloned()
+                .collect(),
+            p: Some(1),
+            q: None,
+            r: [1, 2, 3],
+        };
+        for config in [config, config.length_prefixed(true)] {
+            let bytes = serializer::to_bytes_with_config(&random, config).unwrap();
+            let deserialized_random =
+                deserializer::from_bytes_with_config::<Random>(&bytes, config).unwrap();
+            assert_eq!(random, deserialized_random);
+        }
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Borrowed<'a> {
+        name: &'a str,
+        #[serde(borrow)]
+        nickname: Cow<'a, str>,
+        #[serde(borrow)]
+        data: &'a serde_bytes::Bytes,
+    }
+
+    #[test]
+    fn borrowed() {
+        let config = Config::new().byte_aligned(true);
+        let borrowed = Borrowed {
+            name: "Ayush",
+            nickname: Cow::Borrowed("ayu"),
+            data: serde_bytes::Bytes::new(&[1, 2, 3]),
+        };
+
+        let bytes = serializer::to_bytes_with_config(&borrowed, config).unwrap();
+        let deserialized_borrowed =
+            deserializer::from_bytes_with_config::<Borrowed>(&bytes, config).unwrap();
+        assert_eq!(borrowed, deserialized_borrowed);
+        assert!(bytes
+            .as_ptr_range()
+            .contains(&deserialized_borrowed.name.as_ptr()));
+        assert!(matches!(deserialized_borrowed.nickname, Cow::Borrowed(_)));
+        assert!(bytes
+            .as_ptr_range()
+            .contains(&deserialized_borrowed.data.as_ptr()));
+
+        // strings that start at a byte boundary are borrowed without the byte-aligned mode too.
+        let bytes = serializer::to_bytes(&"hello").unwrap();
+        assert_eq!(deserializer::from_bytes::<&str>(&bytes).unwrap(), "hello");
+        let bytes = serializer::to_bytes(&"").unwrap();
+        assert_eq!(deserializer::from_bytes::<&str>(&bytes).unwrap(), "");
+
+        // ...and copied otherwise.
+        let bytes = serializer::to_bytes(&(true, "hello")).unwrap();
+        let (_, nickname) = deserializer::from_bytes::<(bool, Cow<str>)>(&bytes).unwrap();
+        assert!(matches!(nickname, Cow::Owned(_)));
+        assert_eq!(nickname, "hello");
+    }
+
+    #[test]
+    fn bits() {
+        // write values of every width at every bit offset and read them back, in both orders.
+        for order in [BitOrder::Lsb0, BitOrder::Msb0] {
+            let mut writer = BitWriter::new(Vec::new(), order);
+            for offset in 0..8 {
+                writer.write_bits(0, offset).unwrap();
+                for n in 1..=64 {
+                    writer
+                        .write_bits(u64::MAX.wrapping_mul(n as u64 + 7), n)
+                        .unwrap();
+                }
+                writer.write_bytes(&[0xab; 19]).unwrap();
+            }
+            let bytes = writer.finish().unwrap();
+
+            let mut reader = BitReader::new(&bytes, order);
+            for offset in 0..8 {
+                assert_eq!(reader.read_bits(offset).unwrap(), 0);
+                for n in 1..=64 {
+                    let expected = u64::MAX.wrapping_mul(n as u64 + 7);
+                    let expected = if n == 64 {
+                        expected
+                    } else {
+                        expected & ((1 << n) - 1)
+                    };
+                    assert_eq!(reader.read_bits(n).unwrap(), expected);
+                }
+                let mut read = [0u8; 19];
+                reader.read_bytes(&mut read).unwrap();
+                assert_eq!(read, [0xab; 19]);
+            }
+            assert!(reader.remaining() < 8);
+            assert!(reader.read_bits(8).is_err());
+
+            // bits copied out of a reader are written back the same.
+            let mut copy = Vec::new();
+            reader.copy_bits(3, 203, &mut copy);
+            let mut writer = BitWriter::new(Vec::new(), order);
+            writer.write_bits(0, 3).unwrap();
+            writer.write_bit_slice(&copy, 200).unwrap();
+            let copied = writer.finish().unwrap();
+            // the first 3 bits of a byte are its lowest bits (Lsb0) or its highest bits (Msb0).
+            let rest: u8 = match order {
+                BitOrder::Lsb0 => 0b1111_1000,
+                BitOrder::Msb0 => 0b0001_1111,
+            };
+            assert_eq!(copied[0], bytes[0] & rest);
+            assert_eq!(copied[1..25], bytes[1..25]);
+            assert_eq!(copied[25], bytes[25] & !rest);
+        }
+    }
+
+    #[test]
+    fn serialized_size() {
+        fn check<T: Serialize>(value: &T, config: Config) {
+            let bytes = serializer::to_bytes_with_config(value, config).unwrap();
+            let size = serializer::serialized_size_with_config(value, config).unwrap();
+            let bits = serializer::serialized_bit_size_with_config(value, config).unwrap();
+            assert_eq!(size, bytes.len() as u64);
+            assert_eq!(bits.div_ceil(8), size);
+        }
+
+        let data = PlaygroundData {
+            never: (0..10)
+                .map(|i| (i.to_string(), vec![i as u8; 10]))
+                .collect(),
+            gonna: (0..10).map(|i| i as u8).collect(),
+            give: Some(1),
+            you: false,
+            up: None,
+        };
+        let configs = [
+            Config::new(),
+            Config::new().length_prefixed(true),
+            Config::new().byte_aligned(true),
+        ];
+        for config in configs {
+            check(&data, config);
+            check(&(true, 1u16, "hello", vec![1u8; 100]), config);
+            check(&HashMap::from([(1u8, "one"), (2, "two")]), config);
+        }
+
+        // SEQ_DELIMITER (3) + u8 (8) + SEQ_VALUE_DELIMITER (3) + u8 (8) + SEQ_DELIMITER (3)
+        assert_eq!(serializer::serialized_bit_size(&vec![1u8, 2]).unwrap(), 25);
+        assert_eq!(serializer::serialized_size(&vec![1u8, 2]).unwrap(), 4);
+    }
+
+    #[test]
+    fn buffers() {
+        let value = (true, 1u16, "hello", vec![1u8, 2, 3]);
+        let expected = serializer::to_bytes(&value).unwrap();
+
+        // to_slice: exact fit, bigger buffer and a buffer that is too small.
+        let mut buffer = vec![0u8; expected.len()];
+        assert_eq!(
+            serializer::to_slice(&value, &mut buffer).unwrap(),
+            expected.len()
+        );
+        assert_eq!(buffer, expected);
+        let mut buffer = [0xffu8; 64];
+        let len = serializer::to_slice(&value, &mut buffer).unwrap();
+        assert_eq!(&buffer[..len], expected);
+        let mut buffer = vec![0u8; expected.len() - 1];
+        assert!(matches!(
+            serializer::to_slice(&value, &mut buffer),
+            Err(Error::BufferTooSmall)
+        ));
+
+        // to_vec_in appends.
+        let mut bytes = vec![42];
+        serializer::to_vec_in(&value, &mut bytes).unwrap();
+        assert_eq!(bytes[0], 42);
+        assert_eq!(&bytes[1..], expected);
+
+        // a reused serializer gives the same bytes as to_bytes and keeps its buffer.
+        let mut serializer = serializer::Serializer::new();
+        assert_eq!(serializer.serialize(&value).unwrap(), expected);
+        assert_eq!(
+            serializer.serialize(&"hi").unwrap(),
+            serializer::to_bytes(&"hi").unwrap()
+        );
+        serializer.reset();
+        assert_eq!(serializer.serialize(&value).unwrap(), expected);
+    }
+
+    #[test]
+    fn composition() {
+        // several values through the same serializer and deserializer.
+        let config = Config::new().length_prefixed(true);
+        let mut serializer = crate::Serializer::with_config(config);
+        true.serialize(&mut serializer).unwrap();
+        vec![vec![1u8], vec![2, 3]]
+            .serialize(&mut serializer)
+            .unwrap();
+        "hello".serialize(&mut serializer).unwrap();
+        let bytes = serializer.into_inner().unwrap();
+
+        let mut deserializer = crate::Deserializer::with_config(&bytes, config);
+        assert!(bool::deserialize(&mut deserializer).unwrap());
+        assert!(deserializer.end().is_err());
+        assert_eq!(
+            Vec::<Vec<u8>>::deserialize(&mut deserializer).unwrap(),
+            vec![vec![1], vec![2, 3]]
+        );
+        assert_eq!(String::deserialize(&mut deserializer).unwrap(), "hello");
+        deserializer.end().unwrap();
+
+        // trailing bytes are reported by end.
+        let mut bytes = serializer::to_bytes(&1u8).unwrap();
+        bytes.push(0);
+        let mut deserializer = crate::Deserializer::from_slice(&bytes);
+        assert_eq!(u8::deserialize(&mut deserializer).unwrap(), 1);
+        assert!(matches!(deserializer.end(), Err(Error::TrailingData(8))));
+    }
+
+    #[test]
+    fn seed() {
+        use serde::de::{DeserializeSeed, SeqAccess, Visitor};
+
+        // interns the strings of a sequence and returns their indices.
+        struct Interner<'a>(&'a mut Vec<String>);
+        impl<'de> DeserializeSeed<'de> for Interner<'_> {
+            type Value = Vec<usize>;
+            fn deserialize<D: serde::Deserializer<'de>>(
+                self,
+                d: D,
+            ) -> Result<Vec<usize>, D::Error> {
+                d.deserialize_seq(self)
+            }
+        }
+        impl<'de> Visitor<'de> for Interner<'_> {
+            type Value = Vec<usize>;
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a sequence of strings")
+            }
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<usize>, A::Error> {
+                let mut indices = Vec::new();
+                while let Some(s) = seq.next_element::<String>()? {
+                    match self.0.iter().position(|interned| *interned == s) {
+                        Some(index) => indices.push(index),
+                        None => {
+                            indices.push(self.0.len());
+                            self.0.push(s);
+                        }
+                    }
+                }
+                Ok(indices)
+            }
+        }
+
+        let mut strings = Vec::new();
+        let bytes = serializer::to_bytes(&vec!["a", "b", "a"]).unwrap();
+        let indices = deserializer::from_bytes_seed(Interner(&mut strings), &bytes).unwrap();
+        assert_eq!(indices, vec![0, 1, 0]);
+        #[cfg(feature = "std")]
+        {
+            let bytes = serializer::to_bytes(&vec!["x", "b"]).unwrap();
+            let indices =
+                deserializer::from_reader_seed(Interner(&mut strings), &bytes[..]).unwrap();
+            assert_eq!(indices, vec![2, 1]);
+            assert_eq!(strings, vec!["a", "b", "x"]);
+
+            let bytes = serializer::to_bytes(&(1u8, "hello".to_string())).unwrap();
+            let value: (u8, String) =
+                deserializer::from_reader(std::io::Cursor::new(bytes)).unwrap();
+            assert_eq!(value, (1, "hello".to_string()));
+        }
+    }
+
+    #[test]
+    fn in_place() {
+        let config = Config::new().length_prefixed(true);
+        let old: Vec<String> = (0..1000).map(|i| format!("old entry {i}")).collect();
+        let new: Vec<String> = (0..1000).map(|i| format!("new {i}")).collect();
+
+        let mut place = old.clone();
+        let vec_ptr = place.as_ptr();
+        let string_ptrs: Vec<_> = place.iter().map(|s| s.as_ptr()).collect();
+        let bytes = serializer::to_bytes_with_config(&new, config).unwrap();
+        deserializer::from_bytes_into_with_config(&mut place, &bytes, config).unwrap();
+        assert_eq!(place, new);
+        // the vector and the strings kept their buffers.
+        assert_eq!(place.as_ptr(), vec_ptr);
+        assert!(place
+            .iter()
+            .zip(string_ptrs)
+            .all(|(s, ptr)| s.as_ptr() == ptr));
+
+        // shorter and longer sequences, unaligned strings and the default config.
+        let mut place = vec!["a".to_string(); 3];
+        deserializer::from_bytes_into(&mut place, &serializer::to_bytes(&vec!["xy"]).unwrap())
+            .unwrap();
+        assert_eq!(place, vec!["xy"]);
+        let mut place = (false, String::from("hello"));
+        let bytes = serializer::to_bytes(&(true, "world")).unwrap();
+        deserializer::from_bytes_into(&mut place, &bytes).unwrap();
+        assert_eq!(place, (true, "world".to_string()));
+    }
+
+    #[test]
+    fn envelope() {
+        let value = (true, vec![vec![1u8], vec![2, 3]], "hello".to_string());
+        let config = Config::new().envelope(true).length_prefixed(true);
+        let bytes = serializer::to_bytes_with_config(&value, config).unwrap();
+        // magic + version + flags (length-prefixed)
+        assert_eq!(bytes[..9], [0xf5, b'R', b'F', b'R', 1, 1, 0, 0, 0]);
+        assert_eq!(
+            serializer::serialized_size_with_config(&value, config).unwrap(),
+            bytes.len() as u64
+        );
+        let mut serializer = crate::Serializer::with_config(config);
+        assert_eq!(serializer.serialize(&value).unwrap(), bytes);
+
+        // the deserializer takes the options from the header.
+        type Value = (bool, Vec<Vec<u8>>, String);
+        assert_eq!(deserializer::from_bytes::<Value>(&bytes).unwrap(), value);
+        let aligned = Config::new().envelope(true).byte_aligned(true);
+        let bytes = serializer::to_bytes_with_config(&(1u16, true), aligned).unwrap();
+        assert_eq!(
+            bytes,
+            [0xf5, b'R', b'F', b'R', 1, 2, 0, 0, 0, 3, 1, 0, 4, 1, 3]
+        );
+        assert_eq!(
+            deserializer::from_bytes::<(u16, bool)>(&bytes).unwrap(),
+            (1, true)
+        );
+
+        // unknown versions and flags, missing headers.
+        let mut unknown = bytes.clone();
+        unknown[4] = 2;
+        assert!(matches!(
+            deserializer::from_bytes::<(u16, bool)>(&unknown),
+            Err(Error::UnsupportedVersion(2))
+        ));
+        let mut unknown = bytes.clone();
+        unknown[8] = 0x80;
+        assert!(matches!(
+            deserializer::from_bytes::<(u16, bool)>(&unknown),
+            Err(Error::UnsupportedFlags(0x8000_0002))
+        ));
+        let bare = serializer::to_bytes(&(1u16, true)).unwrap();
+        assert!(matches!(
+            deserializer::from_bytes_with_config::<(u16, bool)>(&bare, aligned),
+            Err(Error::MissingEnvelope)
+        ));
+
+        // a bare payload that starts with the magic bytes is read as one if the envelope is
+        // disabled explicitly; by default it looks like a truncated header.
+        let checksum = Config::new();
+        #[cfg(feature = "checksum")]
+        let checksum = checksum.checksum(crate::config::Checksum::Crc32c);
+        for config in [Config::new(), checksum] {
+            let config = config.envelope(false);
+            let bytes = serializer::to_bytes_with_config(&0x5246_52F5u32, config).unwrap();
+            assert_eq!(bytes[..4], *b"\xF5RFR");
+            let decoded = deserializer::from_bytes_with_config::<u32>(&bytes, config);
+            assert_eq!(decoded.unwrap(), 0x5246_52F5);
+        }
+        let bytes = serializer::to_bytes(&0x5246_52F5u32).unwrap();
+        assert!(matches!(
+            deserializer::from_bytes::<u32>(&bytes),
+            Err(Error::UnexpectedEOF)
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "checksum")]
+    fn checksum() {
+        use crate::config::Checksum;
+
+        // known check values.
+        assert_eq!(Checksum::Crc32c.compute(b"123456789"), 0xe306_9283);
+        assert_eq!(Checksum::XxHash64.compute(b""), 0xef46_db37_51d8_e999);
+
+        let value = (true, vec![1u8, 2, 3], "hello".to_string());
+        type Value = (bool, Vec<u8>, String);
+        for checksum in [Checksum::Crc32c, Checksum::XxHash64] {
+            for envelope in [false, true] {
+                let config = Config::new().checksum(checksum).envelope(envelope);
+                let bytes = serializer::to_bytes_with_config(&value, config).unwrap();
+                let payload = &bytes[..bytes.len() - checksum.size()];
+                assert_eq!(
+                    bytes[payload.len()..],
+                    checksum.compute(payload).to_le_bytes()[..checksum.size()]
+                );
+                let decoded = deserializer::from_bytes_with_config::<Value>(&bytes, config);
+                assert_eq!(decoded.unwrap(), value);
+
+                // every entry point produces the same bytes.
+                let size = serializer::serialized_size_with_config(&value, config).unwrap();
+                assert_eq!(size, bytes.len() as u64);
+                let mut buffer = [0u8; 64];
+                let len = serializer::to_slice_with_config(&value, &mut buffer, config).unwrap();
+                assert_eq!(buffer[..len], bytes);
+                let mut vec = vec![42];
+                serializer::to_vec_in_with_config(&value, &mut vec, config).unwrap();
+                assert_eq!(vec[1..], bytes);
+                let mut serializer = crate::Serializer::with_config(config);
+                assert_eq!(serializer.serialize(&value).unwrap(), bytes);
+
+                // flipped bits are caught before decoding.
+                for bit in 0..bytes.len() * 8 {
+                    let mut corrupted = bytes.clone();
+                    corrupted[bit / 8] ^= 1 << (bit % 8);
+                    let decoded = deserializer::from_bytes_with_config::<Value>(&corrupted, config);
+                    assert!(decoded.is_err());
+                }
+                let corrupted = [&bytes[..bytes.len() - 1], &[!bytes[bytes.len() - 1]]].concat();
+                assert!(matches!(
+                    deserializer::from_bytes_with_config::<Value>(&corrupted, config),
+                    Err(Error::ChecksumMismatch { .. })
+                ));
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "lz4")]
+    fn compression() {
+        use crate::config::Compression;
+
+        let value: Vec<HashMap<String, String>> = (0..100)
+            .map(|i| HashMap::from([("name".to_string(), format!("human {}", i % 10))]))
+            .collect();
+        type Value = Vec<HashMap<String, String>>;
+        let plain = Config::new().length_prefixed(true).envelope(true);
+        let config = plain.compression(Compression::Lz4);
+        #[cfg(feature = "checksum")]
+        let config = config.checksum(crate::config::Checksum::Crc32c);
+        let uncompressed = serializer::to_bytes_with_config(&value, plain).unwrap();
+        let bytes = serializer::to_bytes_with_config(&value, config).unwrap();
+        assert!(bytes.len() * 4 < uncompressed.len());
+        // the envelope tells the deserializer to decompress.
+        assert_eq!(deserializer::from_bytes::<Value>(&bytes).unwrap(), value);
+        #[cfg(feature = "std")]
+        assert_eq!(
+            deserializer::from_reader::<_, Value>(&bytes[..]).unwrap(),
+            value
+        );
+        let mut place = Value::new();
+        deserializer::from_bytes_into(&mut place, &bytes).unwrap();
+        assert_eq!(place, value);
+
+        // every entry point produces the same bytes.
+        let size = serializer::serialized_size_with_config(&value, config).unwrap();
+        assert_eq!(size, bytes.len() as u64);
+        let mut buffer = vec![0u8; bytes.len()];
+        assert_eq!(
+            serializer::to_slice_with_config(&value, &mut buffer, config).unwrap(),
+            bytes.len()
+        );
+        assert_eq!(buffer, bytes);
+        assert!(matches!(
+            serializer::to_slice_with_config(&value, &mut buffer[1..], config),
+            Err(Error::BufferTooSmall)
+        ));
+        let mut vec = vec![42];
+        serializer::to_vec_in_with_config(&value, &mut vec, config).unwrap();
+        assert_eq!(vec[1..], bytes);
+        let mut serializer = crate::Serializer::with_config(config);
+        assert_eq!(serializer.serialize(&value).unwrap(), bytes);
+
+        // decompressed strings can't be borrowed.
+        let config = Config::new().compression(Compression::Lz4);
+        let bytes = serializer::to_bytes_with_config(&"hello", config).unwrap();
+        assert!(deserializer::from_bytes_with_config::<&str>(&bytes, config).is_err());
+        let decoded = deserializer::from_bytes_with_config::<String>(&bytes, config).unwrap();
+        assert_eq!(decoded, "hello");
+    }
+
+    #[test]
+    fn interned_field_names() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        enum Kind {
+            Person { name: String, age: u8 },
+        }
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Human {
+            name: String,
+            age: u8,
+            kind: Kind,
+        }
+
+        let humans: Vec<Human> = (0..100)
+            .map(|i| Human {
+                name: format!("human {i}"),
+                age: i as u8,
+                kind: Kind::Person {
+                    name: "x".to_string(),
+                    age: 1,
+                },
+            })
+            .collect();
+        let length_prefixed = Config::new().length_prefixed(true);
+        for config in [length_prefixed, length_prefixed.byte_aligned(true)] {
+            let interned = config.intern_field_names(true);
+            let bytes = serializer::to_bytes_with_config(&humans, interned).unwrap();
+            let plain = serializer::to_bytes_with_config(&humans, config).unwrap();
+            assert!(bytes.len() < plain.len());
+            let decoded: Vec<Human> =
+                deserializer::from_bytes_with_config(&bytes, interned).unwrap();
+            assert_eq!(decoded, humans);
+        }
+
+        // the field names of a struct variant share the table with the struct: "name" and
+        // "age" are written as 1 + varint(index) (9 bits) instead of str() (40 and 32 bits),
+        // while the three new field names pay 1 bit each.
+        let config = Config::new().length_prefixed(true).intern_field_names(true);
+        let bits = serializer::serialized_bit_size_with_config(&humans[0], config).unwrap();
+        let plain = config.intern_field_names(false);
+        let plain = serializer::serialized_bit_size_with_config(&humans[0], plain).unwrap();
+        assert_eq!(bits, plain + 3 - (40 - 9) - (32 - 9));
+
+        // an unknown index is an error: a map of one field (1 + varint(1)) whose field name
+        // refers back to index 5 (1 + varint(5)).
+        let mut serializer = crate::Serializer::with_config(config);
+        true.serialize(&mut serializer).unwrap();
+        1u8.serialize(&mut serializer).unwrap();
+        true.serialize(&mut serializer).unwrap();
+        5u8.serialize(&mut serializer).unwrap();
+        let bytes = serializer.into_inner().unwrap();
+        let mut bad = crate::Deserializer::with_config(&bytes, config);
+        let result = serde::de::Deserializer::deserialize_struct(
+            &mut bad,
+            "Human",
+            &["name"],
+            serde::de::IgnoredAny,
+        );
+        assert!(matches!(result, Err(Error::UnknownFieldName(5))));
+    }
+
+    #[test]
+    fn packed_sequences() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Id(u32);
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Sample {
+            flags: Vec<bool>,
+            ids: Vec<Id>,
+            values: Vec<f64>,
+            letters: Vec<char>,
+            names: Vec<String>,
+            empty: Vec<u8>,
+            pair: (u8, u16),
+            last: bool,
+        }
+
+        let sample = Sample {
+            flags: (0..13).map(|i| i % 3 == 0).collect(),
+            ids: vec![Id(1), Id(u32::MAX)],
+            values: vec![1.5, -0.25],
+            letters: vec!['r', 'ü'],
+            names: vec!["x".to_string(), "ab".to_string()],
+            empty: vec![],
+            pair: (1, 2),
+            last: true,
+        };
+        for config in [
+            Config::new(),
+            Config::new().length_prefixed(true),
+            Config::new().byte_aligned(true),
+            Config::new().length_prefixed(true).byte_aligned(true),
+        ] {
+            let packed = config.packed_sequences(true);
+            let bytes = serializer::to_bytes_with_config(&sample, packed).unwrap();
+            let decoded: Sample = deserializer::from_bytes_with_config(&bytes, packed).unwrap();
+            assert_eq!(decoded, sample);
+        }
+
+        // SEQ_DELIMITER (3 bits) + kind (4 bits) + varint(100) (8 bits) + 1 bit per bool.
+        let flags = vec![true; 100];
+        let config = Config::new().packed_sequences(true);
+        let bits = serializer::serialized_bit_size_with_config(&flags, config).unwrap();
+        assert_eq!(bits, 3 + 4 + 8 + 100);
+        // byte-aligned: SEQ_DELIMITER + kind + varint(100) + 13 bytes of bools.
+        let config = config.byte_aligned(true);
+        assert_eq!(
+            serializer::serialized_size_with_config(&flags, config).unwrap(),
+            16
+        );
+
+        // f16 is not packed (as the f32 it wraps) and keeps its 2 bytes: SEQ_DELIMITER +
+        // length header (1 bit + varint(3)) + kind + 3 * 16 bits.
+        #[cfg(feature = "f16")]
+        {
+            let halves = vec![crate::f16::from_f32(0.5); 3];
+            let config = Config::new().length_prefixed(true).packed_sequences(true);
+            let bits = serializer::serialized_bit_size_with_config(&halves, config).unwrap();
+            assert_eq!(bits, 3 + 1 + 8 + 4 + 3 * 16);
+            let bytes = serializer::to_bytes_with_config(&halves, config).unwrap();
+            let decoded: Vec<crate::f16> =
+                deserializer::from_bytes_with_config(&bytes, config).unwrap();
+            assert_eq!(decoded, halves);
+        }
+
+        // elements that change their type are written unpacked.
+        #[derive(Debug, PartialEq)]
+        struct Mixed(u8, u8, u16);
+        impl Serialize for Mixed {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                use serde::ser::SerializeSeq;
+                let mut seq = serializer.serialize_seq(Some(3))?;
+                seq.serialize_element(&self.0)?;
+                seq.serialize_element(&self.1)?;
+                seq.serialize_element(&self.2)?;
+                seq.end()
+            }
+        }
+        impl<'de> Deserialize<'de> for Mixed {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                struct MixedVisitor;
+                impl<'de> serde::de::Visitor<'de> for MixedVisitor {
+                    type Value = Mixed;
+                    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                        f.write_str("two u8 and a u16")
+                    }
+                    fn visit_seq<A: serde::de::SeqAccess<'de>>(
+                        self,
+                        mut seq: A,
+                    ) -> Result<Mixed, A::Error> {
+                        let missing = || serde::de::Error::custom("missing element");
+                        let a = seq.next_element()?.ok_or_else(missing)?;
+                        let b = seq.next_element()?.ok_or_else(missing)?;
+                        let c = seq.next_element()?.ok_or_else(missing)?;
+                        match seq.next_element::<u8>()? {
+                            None => Ok(Mixed(a, b, c)),
+                            Some(_) => Err(serde::de::Error::custom("trailing element")),
+                        }
+                    }
+                }
+                deserializer.deserialize_seq(MixedVisitor)
+            }
+        }
+        for config in [
+            Config::new(),
+            Config::new().length_prefixed(true),
+            Config::new().byte_aligned(true),
+            Config::new().unambiguous(true),
+        ] {
+            let packed = config.packed_sequences(true);
+            let mixed = Mixed(1, 2, 300);
+            let bytes = serializer::to_bytes_with_config(&mixed, packed).unwrap();
+            let decoded: Mixed = deserializer::from_bytes_with_config(&bytes, packed).unwrap();
+            assert_eq!(decoded, mixed);
+        }
+    }
+
+    #[test]
+    fn delta() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Series {
+            #[serde(with = "crate::delta")]
+            timestamps: Vec<u64>,
+            #[serde(with = "crate::delta")]
+            offsets: Vec<i64>,
+            #[serde(with = "crate::delta")]
+            edges: Vec<i32>,
+        }
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct NarrowSeries {
+            #[serde(with = "crate::delta")]
+            timestamps: Vec<u64>,
+            #[serde(with = "crate::delta")]
+            offsets: Vec<i8>,
+            #[serde(with = "crate::delta")]
+            edges: Vec<i32>,
+        }
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct PlainSeries {
+            timestamps: Vec<u64>,
+            offsets: Vec<i64>,
+            edges: Vec<i32>,
+        }
+
+        let series = Series {
+            timestamps: (0..1000)
+                .map(|i| 1_700_000_000_000 + i * 1000 + i % 7)
+                .collect(),
+            offsets: (0..1000).map(|i| 500 - i * 3).collect(),
+            edges: vec![i32::MIN, i32::MAX, 0, -1, i32::MIN],
+        };
+        let plain = PlainSeries {
+            timestamps: series.timestamps.clone(),
+            offsets: series.offsets.clone(),
+            edges: series.edges.clone(),
+        };
+        for config in [
+            Config::new(),
+            Config::new().length_prefixed(true).byte_aligned(true),
+            Config::new().packed_sequences(true),
+        ] {
+            let bytes = serializer::to_bytes_with_config(&series, config).unwrap();
+            let plain = serializer::to_bytes_with_config(&plain, config).unwrap();
+            assert!(bytes.len() * 5 < plain.len());
+            let decoded: Series = deserializer::from_bytes_with_config(&bytes, config).unwrap();
+            assert_eq!(decoded, series);
+        }
+
+        // other formats get the blob as bytes.
+        let json = serde_json::to_string(&series).unwrap();
+        assert_eq!(serde_json::from_str::<Series>(&json).unwrap(), series);
+
+        // values out of the range of the type are an error.
+        let bytes = serializer::to_bytes(&Series {
+            timestamps: vec![],
+            offsets: vec![i64::MAX],
+            edges: vec![],
+        })
+        .unwrap();
+        let result = deserializer::from_bytes::<NarrowSeries>(&bytes);
+        assert!(matches!(
+            result,
+            Err(Error::DeserializationError(message))
+                if message.contains("expected a delta encoded sequence of integers")
+        ));
+    }
+
+    #[test]
+    fn compact_floats() {
+        #[derive(Debug, Serialize, Deserialize)]
+        struct Reading {
+            values: Vec<f64>,
+            single: f32,
+            #[cfg(feature = "f16")]
+            half: crate::f16,
+        }
+
+        let reading = Reading {
+            values: vec![
+                0.0,
+                -0.0,
+                1.0,
+                -3.0,
+                1000.0,
+                0.5,
+                0.1,
+                f64::from(1.1f32),
+                1e300,
+                f64::INFINITY,
+                f64::NAN,
+                f64::from_bits(0x7ff0_0000_0000_0001),
+                9.0e18,
+            ],
+            single: 0.1,
+            #[cfg(feature = "f16")]
+            half: crate::f16::from_f32(-2.5),
+        };
+        let length_prefixed = Config::new().length_prefixed(true);
+        for config in [length_prefixed, length_prefixed.byte_aligned(true)] {
+            let compact = config.compact_floats(true);
+            let bytes = serializer::to_bytes_with_config(&reading, compact).unwrap();
+            let plain = serializer::to_bytes_with_config(&reading, config).unwrap();
+            assert!(bytes.len() < plain.len());
+            let decoded: Reading = deserializer::from_bytes_with_config(&bytes, compact).unwrap();
+            let bits = |values: &[f64]| values.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+            assert_eq!(bits(&decoded.values), bits(&reading.values));
+            assert_eq!(decoded.single.to_bits(), reading.single.to_bits());
+            #[cfg(feature = "f16")]
+            assert_eq!(decoded.half, reading.half);
+        }
+
+        // tag (2 bits) + the shortest lossless representation.
+        let config = Config::new().compact_floats(true);
+        for (value, bits) in [
+            (1.0, 2 + 8),
+            (1000.0, 2 + 16),
+            (-0.0, 2 + 16),
+            (0.5, 2 + 16),
+            (f64::from(1.1f32), 2 + 32),
+            (0.1, 2 + 64),
+        ] {
+            let size = serializer::serialized_bit_size_with_config(&value, config).unwrap();
+            assert_eq!(size, bits);
+        }
+        let size = serializer::serialized_bit_size_with_config(&0.1f32, config).unwrap();
+        assert_eq!(size, 2 + 32);
+
+        // f16 is 2 bytes whatever the config is, and an f32 for other formats.
+        #[cfg(feature = "f16")]
+        {
+            let half = crate::f16::from_f32(0.5);
+            assert_eq!(serializer::serialized_size(&half).unwrap(), 2);
+            let json = serde_json::to_string(&half).unwrap();
+            assert_eq!(json, "0.5");
+            assert_eq!(serde_json::from_str::<crate::f16>(&json).unwrap(), half);
+        }
+
+        // the f16 conversions of compact floats agree with `half` for every f16.
+        #[cfg(feature = "f16")]
+        for bits in 0..=u16::MAX {
+            let value = crate::float::from_f16_bits(bits);
+            assert_eq!(
+                value.to_bits(),
+                crate::f16::from_bits(bits).to_f64().to_bits()
+            );
+            // the value itself and its neighbour, which is never an f16.
+            for value in [value, f64::from_bits(value.to_bits() ^ 1)] {
+                let half = crate::f16::from_f64(value).to_bits();
+                let exact = crate::f16::from_bits(half).to_f64().to_bits() == value.to_bits();
+                assert_eq!(crate::float::exact_f16_bits(value), exact.then_some(half));
+            }
+        }
+    }
+
+    #[test]
+    fn canonical_floats() {
+        let nans = [
+            f64::NAN,
+            -f64::NAN,
+            f64::from_bits(0x7ff0_0000_0000_0001),
+            f64::from_bits(0xfff8_dead_beef_0000),
+        ];
+        let length_prefixed = Config::new().length_prefixed(true);
+        for config in [
+            length_prefixed,
+            length_prefixed.compact_floats(true),
+            length_prefixed.packed_sequences(true),
+        ] {
+            // NaN payloads survive by default.
+            let preserved = nans.to_vec();
+            let bytes = serializer::to_bytes_with_config(&preserved, config).unwrap();
+            let decoded: Vec<f64> = deserializer::from_bytes_with_config(&bytes, config).unwrap();
+            let bits = |values: &[f64]| values.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+            assert_eq!(bits(&decoded), bits(&preserved));
+
+            // every NaN (and -0.0) serializes to the same bytes once canonicalized.
+            let canonical = config
+                .nan_policy(NanPolicy::Canonicalize)
+                .normalize_negative_zero(true);
+            let expected = serializer::to_bytes_with_config(&vec![f64::NAN], canonical).unwrap();
+            for nan in nans {
+                let bytes = serializer::to_bytes_with_config(&vec![nan], canonical).unwrap();
+                assert_eq!(bytes, expected);
+            }
+            let zero = serializer::to_bytes_with_config(&vec![0.0f64], canonical).unwrap();
+            let negative = serializer::to_bytes_with_config(&vec![-0.0f64], canonical).unwrap();
+            assert_eq!(negative, zero);
+            let negative = serializer::to_bytes_with_config(&vec![-0.0f64], config).unwrap();
+            assert_ne!(negative, zero);
+
+            // NaNs are an error in strict mode.
+            let strict = config.nan_policy(NanPolicy::Reject);
+            let result = serializer::to_bytes_with_config(&vec![1.0, f64::NAN], strict);
+            assert!(matches!(result, Err(Error::NanNotAllowed)));
+            let result = serializer::to_bytes_with_config(&vec![f32::NAN], strict);
+            assert!(matches!(result, Err(Error::NanNotAllowed)));
+
+            // a rejected f16 doesn't turn the next f32 into an f16.
+            #[cfg(feature = "f16")]
+            {
+                let mut serializer = crate::Serializer::with_config(strict);
+                let result = serializer.serialize(&crate::f16::from_f32(f32::NAN));
+                assert!(matches!(result, Err(Error::NanNotAllowed)));
+                let bytes = serializer.serialize(&1.0f32).unwrap().to_vec();
+                assert_eq!(
+                    bytes,
+                    serializer::to_bytes_with_config(&1.0f32, strict).unwrap()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn canonical_maps() {
+        use std::collections::BTreeMap;
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Index {
+            name: String,
+            entries: HashMap<String, u32>,
+        }
+
+        let pairs: Vec<(String, u32)> = (0..50).map(|i| (format!("key {i}"), i)).collect();
+        let forward = Index {
+            name: "index".to_string(),
+            entries: pairs.iter().cloned().collect(),
+        };
+        let backward = Index {
+            name: "index".to_string(),
+            entries: pairs.iter().rev().cloned().collect(),
+        };
+        for config in [
+            Config::new(),
+            Config::new().length_prefixed(true),
+            Config::new().byte_aligned(true),
+        ] {
+            let canonical = config.canonical(true);
+            let bytes = serializer::to_bytes_with_config(&forward, canonical).unwrap();
+            let other = serializer::to_bytes_with_config(&backward, canonical).unwrap();
+            assert_eq!(bytes, other);
+            let sorted: BTreeMap<String, u32> = pairs.iter().cloned().collect();
+            let sorted = serializer::to_bytes_with_config(&sorted, canonical).unwrap();
+            let entries = serializer::to_bytes_with_config(&forward.entries, canonical).unwrap();
+            assert_eq!(sorted, entries);
+            let decoded: Index = deserializer::from_bytes_with_config(&bytes, canonical).unwrap();
+            assert_eq!(decoded, forward);
+        }
+
+        // keys are sorted by their encoding ("a" + STRING_DELIMITER > "ab" + STRING_DELIMITER),
+        // so the order of a BTreeMap is not canonical.
+        let config = Config::new().length_prefixed(true);
+        let canonical = config.canonical(true);
+        let map: BTreeMap<String, u8> = [("a".to_string(), 1), ("ab".to_string(), 2)].into();
+        let bytes = serializer::to_bytes_with_config(&map, config).unwrap();
+        let result =
+            deserializer::from_bytes_with_config::<BTreeMap<String, u8>>(&bytes, canonical);
+        assert!(matches!(result, Err(Error::UnsortedKeys)));
+        let bytes = serializer::to_bytes_with_config(&map, canonical).unwrap();
+        let decoded: BTreeMap<String, u8> =
+            deserializer::from_bytes_with_config(&bytes, canonical).unwrap();
+        assert_eq!(decoded, map);
+
+        // duplicate keys are rejected on both sides.
+        let serialize_duplicates = |config| {
+            use serde::ser::{SerializeMap, Serializer};
+            let mut serializer = crate::Serializer::with_config(config);
+            let mut map = (&mut serializer).serialize_map(Some(2))?;
+            map.serialize_entry("x", &1u8)?;
+            map.serialize_entry("x", &2u8)?;
+            map.end()?;
+            serializer.into_inner()
+        };
+        assert!(matches!(
+            serialize_duplicates(canonical),
+            Err(Error::DuplicateKey)
+        ));
+        let bytes = serialize_duplicates(config).unwrap();
+        let result = deserializer::from_bytes_with_config::<HashMap<String, u8>>(&bytes, canonical);
+        assert!(matches!(result, Err(Error::DuplicateKey)));
+
+        // the keys are encoded on their own, so field names can't be interned.
+        let result = serializer::to_bytes_with_config(&map, canonical.intern_field_names(true));
+        assert!(matches!(result, Err(Error::IncompatibleConfig(_))));
+    }
+
+    #[test]
+    fn hash() {
+        use std::hash::{DefaultHasher, Hasher};
+
+        #[derive(Serialize)]
+        struct Settings {
+            name: String,
+            limits: HashMap<String, u64>,
+        }
+
+        let limits: Vec<(String, u64)> = (0..20).map(|i| (format!("limit {i}"), i)).collect();
+        let settings = Settings {
+            name: "settings".to_string(),
+            limits: limits.iter().cloned().collect(),
+        };
+        let reordered = Settings {
+            name: "settings".to_string(),
+            limits: limits.iter().rev().cloned().collect(),
+        };
+        let hash = crate::hash::<DefaultHasher, _>(&settings).unwrap();
+        assert_eq!(crate::hash::<DefaultHasher, _>(&reordered).unwrap(), hash);
+
+        // the same as hashing the canonical bytes at once.
+        let canonical = Config::new().canonical(true);
+        let mut hasher = DefaultHasher::new();
+        hasher.write(&serializer::to_bytes_with_config(&settings, canonical).unwrap());
+        assert_eq!(hasher.finish(), hash);
+
+        let changed = Settings {
+            name: "changed".to_string(),
+            limits: HashMap::new(),
+        };
+        assert_ne!(crate::hash::<DefaultHasher, _>(&changed).unwrap(), hash);
+
+        // with the output of any config, checksum included.
+        let config = canonical.envelope(true);
+        #[cfg(feature = "checksum")]
+        let config = config.checksum(crate::config::Checksum::XxHash64);
+        let mut hasher = DefaultHasher::new();
+        serializer::hash_into_with_config(&settings, &mut hasher, config).unwrap();
+        let mut expected = DefaultHasher::new();
+        expected.write(&serializer::to_bytes_with_config(&settings, config).unwrap());
+        assert_eq!(hasher.finish(), expected.finish());
+    }
+
+    #[test]
+    fn big_endian() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        enum Direction {
+            Up,
+            Down,
+        }
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Frame {
+            id: u32,
+            offset: i16,
+            scale: f64,
+            level: f32,
+            #[cfg(feature = "f16")]
+            half: crate::f16,
+            letter: char,
+            direction: Direction,
+            samples: Vec<u16>,
+        }
+
+        let big = Config::new().endian(Endian::Big);
+        let bytes = serializer::to_bytes_with_config(&0x0102_0304u32, big).unwrap();
+        assert_eq!(bytes, [1, 2, 3, 4]);
+        let bytes = serializer::to_bytes_with_config(&-2i16, big).unwrap();
+        assert_eq!(bytes, [0xff, 0xfe]);
+        let bytes = serializer::to_bytes_with_config(&1.0f64, big).unwrap();
+        assert_eq!(bytes, [0x3f, 0xf0, 0, 0, 0, 0, 0, 0]);
+        let bytes = serializer::to_bytes_with_config(&'A', big).unwrap();
+        assert_eq!(bytes, [0, 0, 0, b'A']);
+        let bytes = serializer::to_bytes_with_config(&Direction::Down, big).unwrap();
+        assert_eq!(bytes, [0, 0, 0, 1]);
+        #[cfg(feature = "f16")]
+        {
+            let half = crate::f16::from_f32(1.0);
+            let bytes = serializer::to_bytes_with_config(&half, big).unwrap();
+            assert_eq!(bytes, [0x3c, 0]);
+        }
+
+        let frame = Frame {
+            id: 0xdead_beef,
+            offset: -300,
+            scale: 0.1,
+            level: 1.5,
+            #[cfg(feature = "f16")]
+            half: crate::f16::from_f32(-2.0),
+            letter: 'ü',
+            direction: Direction::Up,
+            samples: vec![1, 0x0200, u16::MAX],
+        };
+        let length_prefixed = big.length_prefixed(true);
+        for config in [
+            length_prefixed,
+            length_prefixed.byte_aligned(true),
+            length_prefixed.packed_sequences(true),
+            length_prefixed.compact_floats(true),
+            length_prefixed.envelope(true),
+        ] {
+            let bytes = serializer::to_bytes_with_config(&frame, config).unwrap();
+            let little = config.endian(Endian::Little);
+            assert_ne!(
+                bytes,
+                serializer::to_bytes_with_config(&frame, little).unwrap()
+            );
+            let decoded: Frame = deserializer::from_bytes_with_config(&bytes, config).unwrap();
+            assert_eq!(decoded, frame);
+        }
+    }
+
+    #[test]
+    fn bit_order() {
+        use std::collections::BTreeMap;
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        enum Signal {
+            Idle,
+            Level(f32),
+            Burst { count: u16, flags: Vec<bool> },
+        }
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Capture {
+            valid: bool,
+            channel: u8,
+            label: String,
+            signals: Vec<Signal>,
+            gains: BTreeMap<String, f64>,
+        }
+
+        let msb0 = Config::new().bit_order(BitOrder::Msb0);
+        // bits fill the bytes from the top: a bool followed by padding...
+        assert_eq!(serializer::to_bytes(&true).unwrap(), [0b0000_0001]);
+        assert_eq!(
+            serializer::to_bytes_with_config(&true, msb0).unwrap(),
+            [0b1000_0000]
+        );
+        // ...and values are written most significant bit first: the Seq delimiter (011), then true.
+        let bools = [true, false];
+        assert_eq!(serializer::to_bytes(&bools).unwrap()[0] & 0b1111, 0b1011);
+        assert_eq!(
+            serializer::to_bytes_with_config(&bools, msb0).unwrap()[0] >> 4,
+            0b0111
+        );
+
+        let capture = Capture {
+            valid: true,
+            channel: 5,
+            label: "adc0".into(),
+            signals: vec![
+                Signal::Idle,
+                Signal::Level(-1.25),
+                Signal::Burst {
+                    count: 300,
+                    flags: vec![true, false, true],
+                },
+            ],
+            gains: BTreeMap::from([("a".into(), 0.5), ("b".into(), 3.0)]),
+        };
+        for config in [
+            Config::new(),
+            Config::new().length_prefixed(true),
+            Config::new().byte_aligned(true),
+            Config::new().packed_sequences(true).length_prefixed(true),
+            Config::new().compact_floats(true).length_prefixed(true),
+            Config::new().canonical(true).endian(Endian::Big),
+            Config::new().intern_field_names(true).envelope(true),
+        ] {
+            let lsb0 = serializer::to_bytes_with_config(&capture, config).unwrap();
+            let msb0 = config.bit_order(BitOrder::Msb0);
+            let bytes = serializer::to_bytes_with_config(&capture, msb0).unwrap();
+            assert_eq!(bytes.len(), lsb0.len());
+            // byte-aligned values come out the same whatever the bit order is.
+            assert_eq!(bytes == lsb0, config.byte_aligned);
+            let decoded: Capture = deserializer::from_bytes_with_config(&bytes, msb0).unwrap();
+            assert_eq!(decoded, capture);
+        }
+
+        // the envelope records the bit order.
+        let config = msb0.envelope(true);
+        let bytes = serializer::to_bytes_with_config(&capture, config).unwrap();
+        assert_eq!(
+            deserializer::from_bytes::<Capture>(&bytes).unwrap(),
+            capture
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn async_io() {
+        use crate::codec::RustFrCodec;
+        use futures::{SinkExt, StreamExt};
+        use tokio_util::codec::{FramedRead, FramedWrite};
+
+        #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+        enum Message {
+            Ping(u32),
+            Data { topic: String, payload: Vec<u8> },
+            Bye,
+        }
+
+        // a single message, read until the writer is dropped.
+        let config = Config::new().length_prefixed(true);
+        let (client, mut server) = tokio::io::duplex(16);
+        let message = Message::Data {
+            topic: "sensors".into(),
+            payload: (0..100).collect(),
+        };
+        let write = async move {
+            let mut client = client;
+            serializer::to_async_writer_with_config(&message, &mut client, config).await
+        };
+        let read = deserializer::from_async_reader_with_config::<_, Message>(&mut server, config);
+        let (written, read) = tokio::join!(write, read);
+        written.unwrap();
+        assert!(matches!(read.unwrap(), Message::Data { payload, .. } if payload.len() == 100));
+
+        // many messages over one stream (smaller than some of them) with the codec.
+        let messages = vec![
+            Message::Ping(1),
+            Message::Data {
+                topic: "a".into(),
+                payload: vec![0x87; 300],
+            },
+            Message::Bye,
+            Message::Ping(u32::MAX),
+        ];
+        let config = config.envelope(true);
+        #[cfg(feature = "checksum")]
+        let config = config.checksum(crate::config::Checksum::Crc32c);
+        let (client, server) = tokio::io::duplex(64);
+        let mut sink = FramedWrite::new(client, RustFrCodec::<Message>::with_config(config));
+        let mut stream = FramedRead::new(server, RustFrCodec::<Message>::with_config(config));
+        let sent = messages.clone();
+        let send = async move {
+            for message in sent {
+                sink.send(message).await.unwrap();
+            }
+        };
+        let receive = async {
+            let mut received = Vec::new();
+            while let Some(message) = stream.next().await {
+                received.push(message.unwrap());
+            }
+            received
+        };
+        let ((), received) = tokio::join!(send, receive);
+        assert_eq!(received, messages);
+
+        // frames over the maximum length are rejected on both sides.
+        let (client, server) = tokio::io::duplex(1024);
+        let mut sink = FramedWrite::new(client, RustFrCodec::new().max_frame_length(16));
+        let big = Message::Data {
+            topic: "big".into(),
+            payload: vec![1; 32],
+        };
+        let error = sink.send(big.clone()).await.unwrap_err();
+        assert!(matches!(error, Error::FrameTooLarge(_, 16)));
+        let mut sink = FramedWrite::new(sink.into_inner(), RustFrCodec::new());
+        sink.send(big).await.unwrap();
+        let mut stream =
+            FramedRead::new(server, RustFrCodec::<Message>::new().max_frame_length(16));
+        let error = stream.next().await.unwrap().unwrap_err();
+        assert!(matches!(error, Error::FrameTooLarge(_, 16)));
+    }
+
+    #[cfg(feature = "futures-io")]
+    #[test]
+    fn futures_io() {
+        use futures::io::Cursor;
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Message {
+            topic: String,
+            payload: Vec<u8>,
+        }
+
+        let message = Message {
+            topic: "sensors".into(),
+            payload: (0..100).collect(),
+        };
+        let length_prefixed = Config::new().length_prefixed(true);
+        for config in [Config::new(), length_prefixed.envelope(true)] {
+            let mut writer = Cursor::new(Vec::new());
+            let written = serializer::to_futures_writer_with_config(&message, &mut writer, config);
+            futures::executor::block_on(written).unwrap();
+            let bytes = writer.into_inner();
+            assert_eq!(
+                bytes,
+                serializer::to_bytes_with_config(&message, config).unwrap()
+            );
+            let reader = Cursor::new(bytes);
+            let read = deserializer::from_futures_reader_with_config::<_, Message>(reader, config);
+            assert_eq!(futures::executor::block_on(read).unwrap(), message);
+        }
+
+        // the defaults.
+        let mut writer = Cursor::new(Vec::new());
+        futures::executor::block_on(serializer::to_futures_writer(&message, &mut writer)).unwrap();
+        let reader = Cursor::new(writer.into_inner());
+        let read = deserializer::from_futures_reader::<_, Message>(reader);
+        assert_eq!(futures::executor::block_on(read).unwrap(), message);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn framing() {
+        use crate::framing::{self, FrameReader};
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Record {
+            id: u32,
+            tags: Vec<String>,
+            score: Option<f64>,
+        }
+
+        let records: Vec<Record> = (0..50)
+            .map(|id| Record {
+                id,
+                tags: (0..id % 5).map(|tag| format!("tag {tag}")).collect(),
+                score: (id % 3 == 0).then_some(f64::from(id) / 4.0),
+            })
+            .collect();
+        let config = Config::new().length_prefixed(true).packed_sequences(true);
+        let mut stream = Vec::new();
+        for record in &records {
+            framing::write_frame_with_config(record, &mut stream, config).unwrap();
+        }
+        // every frame is its varint length followed by the message.
+        let first = serializer::to_bytes_with_config(&records[0], config).unwrap();
+        assert_eq!(stream[0] as usize, first.len());
+        assert_eq!(stream[1..=first.len()], first);
+
+        // one frame at a time...
+        let mut reader = &stream[..];
+        let record: Record = framing::read_frame_with_config(&mut reader, config)
+            .unwrap()
+            .unwrap();
+        assert_eq!(record, records[0]);
+        // ...or as an iterator over the rest, which ends with the stream.
+        let frames = FrameReader::<_, Record>::with_config(reader, config);
+        let rest = frames.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(rest, records[1..]);
+        assert!(framing::read_frame::<_, Record>(&[][..]).unwrap().is_none());
+
+        // lengths of more than one byte.
+        let big = vec![7u8; 100_000];
+        let mut stream = Vec::new();
+        framing::write_frame(&big, &mut stream).unwrap();
+        framing::write_frame(&big[..3].to_vec(), &mut stream).unwrap();
+        let size = serializer::serialized_size(&big).unwrap() as usize;
+        let small = serializer::serialized_size(&big[..3].to_vec()).unwrap() as usize;
+        assert_eq!(stream.len(), 3 + size + 1 + small);
+        let frames: Vec<Vec<u8>> = FrameReader::new(&stream[..])
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(frames, [big.clone(), vec![7; 3]]);
+
+        // frames over the maximum are rejected before their bytes are read...
+        let mut frames = FrameReader::<_, Vec<u8>>::new(&stream[..]).max_frame_length(1000);
+        let error = frames.next().unwrap().unwrap_err();
+        assert!(matches!(error, Error::FrameTooLarge(len, 1000) if len == size));
+        // ...and streams that end within a frame are reported.
+        let error = framing::read_frame::<_, Vec<u8>>(&stream[..100]).unwrap_err();
+        assert!(matches!(error, Error::UnexpectedEOF));
+        let error = framing::read_frame::<_, Vec<u8>>(&[0x80, 0x80][..]).unwrap_err();
+        assert!(matches!(error, Error::UnexpectedEOF));
+        let error = framing::read_frame::<_, Vec<u8>>(&[0xff; 11][..]).unwrap_err();
+        assert!(matches!(error, Error::InvalidVarint));
+        // a length over 64 bits is an error too, not truncated.
+        let mut overflow = [0xff; 10];
+        overflow[9] = 0x02;
+        let error = framing::read_frame::<_, Vec<u8>>(&overflow[..]).unwrap_err();
+        assert!(matches!(error, Error::InvalidVarint));
+        overflow[9] = 0x01;
+        let error = framing::read_frame::<_, Vec<u8>>(&overflow[..]).unwrap_err();
+        assert!(matches!(error, Error::FrameTooLarge(len, _) if len == usize::MAX));
+    }
+
+    // counts the allocations of each thread so that a test can check it didn't allocate.
+    struct CountingAllocator;
+
+    std::thread_local! {
+        static ALLOCATIONS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    }
+
+    unsafe impl std::alloc::GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            std::alloc::System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+            std::alloc::System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    #[test]
+    fn to_slice_without_allocating() {
+        #[derive(Serialize)]
+        enum Reading<'a> {
+            Missing,
+            Value(f32),
+            Labelled { label: &'a str, raw: [u16; 3] },
+        }
+
+        let readings = [
+            Reading::Missing,
+            Reading::Value(21.5),
+            Reading::Labelled {
+                label: "probe",
+                raw: [1, 2, 3],
+            },
+        ];
+        let value = (42u64, "sensor", &readings[..], Some('x'));
+        #[cfg(feature = "f16")]
+        let value = (value, crate::f16::from_f32(0.5));
+        let mut buffer = [0u8; 256];
+        let envelope = Config::new().envelope(true);
+        #[cfg(feature = "checksum")]
+        let envelope = envelope.checksum(crate::config::Checksum::XxHash64);
+        for config in [
+            Config::new(),
+            Config::new().length_prefixed(true).byte_aligned(true),
+            envelope,
+            Config::new().packed_sequences(true).compact_floats(true),
+            Config::new().endian(Endian::Big).bit_order(BitOrder::Msb0),
+        ] {
+            let before = ALLOCATIONS.with(|count| count.get());
+            let len = serializer::to_slice_with_config(&value, &mut buffer, config).unwrap();
+            assert_eq!(ALLOCATIONS.with(|count| count.get()), before);
+            let bytes = serializer::to_bytes_with_config(&value, config).unwrap();
+            assert_eq!(buffer[..len], bytes);
+        }
+    }
+
     #[derive(Debug, Serialize, Deserialize, PartialEq)]
     struct PlaygroundData {
         never: HashMap<String, Vec<u8>>,
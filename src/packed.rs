@@ -0,0 +1,417 @@
+//! ### Packed
+//! Internal module for the packed sequences of [`Config::packed_sequences`]. A packed run
+//! holds the elements of a sequence of one fixed-width primitive type back to back: bools
+//! as single bits, everything else as is (in the byte order of the config), without any
+//! delimiters.
+//!
+//! The serializer probes every element with [`Primitive::of`] to find out whether (and as
+//! what) it can be packed, and buffers the run until the sequence ends; a sequence whose
+//! elements change their kind is written unpacked. The deserializer reads the elements back
+//! with [`Primitive::read`] and hands them to the visitor through the [`de::Deserializer`]
+//! impl of [`Primitive`].
+//!
+//! [`Config::packed_sequences`]: crate::config::Config::packed_sequences
+
+use super::{
+    bits::{BitReader, BitWriter, Sink},
+    config::Endian,
+    error::Error,
+    float,
+};
+use core::fmt;
+use serde::{
+    de,
+    ser::{self, Impossible},
+    Serialize,
+};
+
+/// The kind tag of a sequence in packed mode. `Other` sequences are written element by
+/// element as usual, all other kinds are packed runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Kind {
+    Other = 0,
+    Bool = 1,
+    U8 = 2,
+    U16 = 3,
+    U32 = 4,
+    U64 = 5,
+    I8 = 6,
+    I16 = 7,
+    I32 = 8,
+    I64 = 9,
+    F32 = 10,
+    F64 = 11,
+    Char = 12,
+}
+
+impl Kind {
+    /// The number of bits a kind tag takes (unless the config is byte-aligned).
+    pub(crate) const BITS: usize = 4;
+
+    /// Get the kind of the tag.
+    pub(crate) fn from_tag(tag: u8) -> Result<Self, Error> {
+        Ok(match tag {
+            0 => Kind::Other,
+            1 => Kind::Bool,
+            2 => Kind::U8,
+            3 => Kind::U16,
+            4 => Kind::U32,
+            5 => Kind::U64,
+            6 => Kind::I8,
+            7 => Kind::I16,
+            8 => Kind::I32,
+            9 => Kind::I64,
+            10 => Kind::F32,
+            11 => Kind::F64,
+            12 => Kind::Char,
+            _ => return Err(Error::InvalidPackedKind(tag)),
+        })
+    }
+}
+
+/// A fixed-width primitive value, i.e. an element of a packed run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Primitive {
+    Bool(bool),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    Char(char),
+}
+
+impl Primitive {
+    /// Get the value as a primitive if it serializes as a single fixed-width primitive
+    /// (newtype structs are looked through, except `f16`).
+    pub(crate) fn of<T>(value: &T) -> Option<Self>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(Probe).ok()
+    }
+
+    /// Get the kind of the value.
+    pub(crate) fn kind(self) -> Kind {
+        match self {
+            Primitive::Bool(_) => Kind::Bool,
+            Primitive::U8(_) => Kind::U8,
+            Primitive::U16(_) => Kind::U16,
+            Primitive::U32(_) => Kind::U32,
+            Primitive::U64(_) => Kind::U64,
+            Primitive::I8(_) => Kind::I8,
+            Primitive::I16(_) => Kind::I16,
+            Primitive::I32(_) => Kind::I32,
+            Primitive::I64(_) => Kind::I64,
+            Primitive::F32(_) => Kind::F32,
+            Primitive::F64(_) => Kind::F64,
+            Primitive::Char(_) => Kind::Char,
+        }
+    }
+
+    /// Write the value to a packed run: bools as a single bit, everything else as is.
+    pub(crate) fn write<S: Sink>(
+        self,
+        writer: &mut BitWriter<S>,
+        endian: Endian,
+    ) -> Result<(), Error> {
+        match self {
+            Primitive::Bool(v) => writer.write_bit(v),
+            Primitive::U8(v) => writer.write_bytes(&v.to_le_bytes()),
+            Primitive::U16(v) => writer.write_bytes(&endian.order(v.to_le_bytes())),
+            Primitive::U32(v) => writer.write_bytes(&endian.order(v.to_le_bytes())),
+            Primitive::U64(v) => writer.write_bytes(&endian.order(v.to_le_bytes())),
+            Primitive::I8(v) => writer.write_bytes(&v.to_le_bytes()),
+            Primitive::I16(v) => writer.write_bytes(&endian.order(v.to_le_bytes())),
+            Primitive::I32(v) => writer.write_bytes(&endian.order(v.to_le_bytes())),
+            Primitive::I64(v) => writer.write_bytes(&endian.order(v.to_le_bytes())),
+            Primitive::F32(v) => writer.write_bytes(&endian.order(v.to_le_bytes())),
+            Primitive::F64(v) => writer.write_bytes(&endian.order(v.to_le_bytes())),
+            Primitive::Char(v) => writer.write_bytes(&endian.order(u32::from(v).to_le_bytes())),
+        }
+    }
+
+    /// Read a value of the kind from a packed run.
+    pub(crate) fn read(
+        kind: Kind,
+        reader: &mut BitReader<'_>,
+        endian: Endian,
+    ) -> Result<Self, Error> {
+        // read `n` bits (a single bit or whole bytes) as a number in the byte order of the config.
+        let mut read_bits = |n: usize| {
+            if n == 1 {
+                return reader.read_bits(1);
+            }
+            let mut bytes = [0u8; 8];
+            reader.read_bytes(&mut bytes[..n / 8])?;
+            if endian == Endian::Big {
+                bytes[..n / 8].reverse();
+            }
+            Ok(u64::from_le_bytes(bytes))
+        };
+        Ok(match kind {
+            Kind::Other => return Err(Error::InvalidPackedKind(Kind::Other as u8)),
+            Kind::Bool => Primitive::Bool(read_bits(1)? == 1),
+            Kind::U8 => Primitive::U8(read_bits(8)? as u8),
+            Kind::U16 => Primitive::U16(read_bits(16)? as u16),
+            Kind::U32 => Primitive::U32(read_bits(32)? as u32),
+            Kind::U64 => Primitive::U64(read_bits(64)?),
+            Kind::I8 => Primitive::I8(read_bits(8)? as i8),
+            Kind::I16 => Primitive::I16(read_bits(16)? as i16),
+            Kind::I32 => Primitive::I32(read_bits(32)? as i32),
+            Kind::I64 => Primitive::I64(read_bits(64)? as i64),
+            Kind::F32 => Primitive::F32(f32::from_bits(read_bits(32)? as u32)),
+            Kind::F64 => Primitive::F64(f64::from_bits(read_bits(64)?)),
+            Kind::Char => Primitive::Char(
+                char::from_u32(read_bits(32)? as u32).ok_or(Error::ConversionError)?,
+            ),
+        })
+    }
+}
+
+/// Serializes a primitive of a packed run as an ordinary value, for sequences that turn out
+/// not to be packable after all.
+impl Serialize for Primitive {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        match *self {
+            Primitive::Bool(v) => serializer.serialize_bool(v),
+            Primitive::U8(v) => serializer.serialize_u8(v),
+            Primitive::U16(v) => serializer.serialize_u16(v),
+            Primitive::U32(v) => serializer.serialize_u32(v),
+            Primitive::U64(v) => serializer.serialize_u64(v),
+            Primitive::I8(v) => serializer.serialize_i8(v),
+            Primitive::I16(v) => serializer.serialize_i16(v),
+            Primitive::I32(v) => serializer.serialize_i32(v),
+            Primitive::I64(v) => serializer.serialize_i64(v),
+            Primitive::F32(v) => serializer.serialize_f32(v),
+            Primitive::F64(v) => serializer.serialize_f64(v),
+            Primitive::Char(v) => serializer.serialize_char(v),
+        }
+    }
+}
+
+/// Hands a primitive of a packed run to a visitor. Newtype structs are looked through, like
+/// they are when probing.
+impl<'de> de::Deserializer<'de> for Primitive {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        match self {
+            Primitive::Bool(v) => visitor.visit_bool(v),
+            Primitive::U8(v) => visitor.visit_u8(v),
+            Primitive::U16(v) => visitor.visit_u16(v),
+            Primitive::U32(v) => visitor.visit_u32(v),
+            Primitive::U64(v) => visitor.visit_u64(v),
+            Primitive::I8(v) => visitor.visit_i8(v),
+            Primitive::I16(v) => visitor.visit_i16(v),
+            Primitive::I32(v) => visitor.visit_i32(v),
+            Primitive::I64(v) => visitor.visit_i64(v),
+            Primitive::F32(v) => visitor.visit_f32(v),
+            Primitive::F64(v) => visitor.visit_f64(v),
+            Primitive::Char(v) => visitor.visit_char(v),
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+/// The error of [`Probe`]: the value is not a fixed-width primitive.
+#[derive(Debug)]
+struct NotPrimitive;
+
+impl fmt::Display for NotPrimitive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("not a fixed-width primitive")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for NotPrimitive {}
+
+// serde's stand-in for `std::error::Error` without std.
+#[cfg(not(feature = "std"))]
+impl ser::StdError for NotPrimitive {}
+
+impl ser::Error for NotPrimitive {
+    fn custom<T>(_msg: T) -> Self
+    where
+        T: fmt::Display,
+    {
+        NotPrimitive
+    }
+}
+
+/// A serializer that captures a single fixed-width primitive value and fails on anything else.
+struct Probe;
+
+impl ser::Serializer for Probe {
+    type Ok = Primitive;
+    type Error = NotPrimitive;
+
+    type SerializeSeq = Impossible<Primitive, NotPrimitive>;
+    type SerializeTuple = Impossible<Primitive, NotPrimitive>;
+    type SerializeTupleStruct = Impossible<Primitive, NotPrimitive>;
+    type SerializeTupleVariant = Impossible<Primitive, NotPrimitive>;
+    type SerializeMap = Impossible<Primitive, NotPrimitive>;
+    type SerializeStruct = Impossible<Primitive, NotPrimitive>;
+    type SerializeStructVariant = Impossible<Primitive, NotPrimitive>;
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
+        Ok(Primitive::Bool(v))
+    }
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
+        Ok(Primitive::I8(v))
+    }
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
+        Ok(Primitive::I16(v))
+    }
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
+        Ok(Primitive::I32(v))
+    }
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
+        Ok(Primitive::I64(v))
+    }
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
+        Ok(Primitive::U8(v))
+    }
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
+        Ok(Primitive::U16(v))
+    }
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
+        Ok(Primitive::U32(v))
+    }
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
+        Ok(Primitive::U64(v))
+    }
+    fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
+        Ok(Primitive::F32(v))
+    }
+    fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
+        Ok(Primitive::F64(v))
+    }
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        Ok(Primitive::Char(v))
+    }
+    fn serialize_str(self, _v: &str) -> Result<Self::Ok, Self::Error> {
+        Err(NotPrimitive)
+    }
+    fn serialize_bytes(self, _v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        Err(NotPrimitive)
+    }
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        Err(NotPrimitive)
+    }
+    fn serialize_some<T>(self, _value: &T) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        Err(NotPrimitive)
+    }
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        Err(NotPrimitive)
+    }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Self::Error> {
+        Err(NotPrimitive)
+    }
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        Err(NotPrimitive)
+    }
+    fn serialize_newtype_struct<T>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        // an f16 is 2 bytes but would be captured as the f32 it wraps.
+        if name == float::F16_TOKEN {
+            return Err(NotPrimitive);
+        }
+        value.serialize(self)
+    }
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        Err(NotPrimitive)
+    }
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Err(NotPrimitive)
+    }
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        Err(NotPrimitive)
+    }
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        Err(NotPrimitive)
+    }
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        Err(NotPrimitive)
+    }
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Err(NotPrimitive)
+    }
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        Err(NotPrimitive)
+    }
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        Err(NotPrimitive)
+    }
+}
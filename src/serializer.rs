@@ -3,24 +3,34 @@
 //!
 //! To use the serializer, call the [`to_bytes`] function with a reference to the data to be
 //! serialized. The data must implement the `Serialize` trait from the `serde` library.
+//! [`to_slice`] and [`to_vec_in`] write to a buffer provided by the caller instead and a
+//! [`Serializer`] can be reused for many values without allocating a new buffer each time.
 
-use bitvec::{prelude as bv, slice::BitSlice};
+use alloc::{collections::BTreeMap, string::ToString, vec::Vec};
+use core::hash::Hasher;
 use serde::{
     ser::{
-        SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant, SerializeTuple,
+        self, SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant, SerializeTuple,
         SerializeTupleStruct, SerializeTupleVariant,
     },
-    Serialize, Serializer,
+    Serialize,
 };
 
-use super::error::Error;
+use super::{
+    bits::{BitWriter, Discard, HashSink, Sink, SliceSink},
+    config::{Compression, Config},
+    delta, envelope,
+    error::Error,
+    float::{self, Compact},
+    packed::{Kind, Primitive},
+};
 
 /// The delimiter used in the format specification. The purpose
 /// of delimiters is to separate different types of data such
 /// that they don't mangle. There are 8 different delimiters
 /// in the format specification out of which 3 (`String`, `Byte` & `Map`)
 /// are 1 byte long and 5 (the rest...) are 3 bits long.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Delimiter {
     // 0b10000110
     String = 134,
@@ -40,8 +50,8 @@ pub enum Delimiter {
     MapValue = 7,
 }
 
-impl std::fmt::Display for Delimiter {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl core::fmt::Display for Delimiter {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             Delimiter::String => write!(f, "String"),
             Delimiter::Byte => write!(f, "Byte"),
@@ -55,164 +65,765 @@ impl std::fmt::Display for Delimiter {
     }
 }
 
-// Internal struct that handles the serialization of the data.
-// It has a few methods that lets us peeking bytes in the data.
+/// The serializer of the format. Create one with [`Serializer::new`] (or
+/// [`Serializer::with_config`]) and call [`Serializer::serialize`] for each value; the output
+/// buffer is kept between values so its capacity is reused.
+///
+/// `&mut Serializer` also implements `serde::Serializer` so it can be driven directly, e.g.
+/// by `serde_transcode` or `erased-serde`. In that case:
+/// - Values serialized one after another are packed back to back without any separator.
+///   They must be deserialized in the same order with a
+///   [`Deserializer`](crate::deserializer::Deserializer) using the same [`Config`].
+/// - The envelope header of [`Config::envelope`] is not written; only the entry points of
+///   this module (including [`Serializer::serialize`]) write it.
+/// - The last byte is only complete once the serializer is finished with
+///   [`Serializer::into_inner`].
+/// - After an error the content of the buffer is unspecified; [reset](Serializer::reset)
+///   the serializer before using it again.
+///
+/// ```rust
+/// use rust_fr::serializer::Serializer;
+///
+/// let mut serializer = Serializer::new();
+/// for i in 0..3u32 {
+///     let bytes = serializer.serialize(&i).unwrap();
+///     assert_eq!(bytes, i.to_le_bytes());
+/// }
+/// ```
 #[derive(Debug)]
-struct CustomSerializer {
-    data: bv::BitVec<u8, bv::Lsb0>,
+pub struct Serializer<S: Sink = Vec<u8>> {
+    data: BitWriter<S>,
+    config: Config,
+    // the indices of the field names written so far if the config interns them.
+    field_names: BTreeMap<&'static str, usize>,
+    // set while serializing the value of one of the reserved newtype structs.
+    raw: Raw,
+}
+
+/// A value that is written as is because it is wrapped in one of the reserved newtype structs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Raw {
+    None,
+    /// The blob of a delta encoded sequence, see [`delta`](crate::delta).
+    Delta,
+    /// A half-precision float, see `f16`.
+    #[cfg(feature = "f16")]
+    F16,
 }
 
 /// The function to serialize data of a given type to a byte vector. The
 /// `value` must implement the `Serialize` trait from the `serde` library. It returns
 /// a Result with the serialized byte vector or an error.
 pub fn to_bytes<T: Serialize>(value: &T) -> Result<Vec<u8>, Error> {
-    let mut serializer = CustomSerializer {
-        data: bv::BitVec::new(),
-    };
-    value.serialize(&mut serializer)?;
-    Ok(serializer.data.into_vec())
-}
-
-impl CustomSerializer {
-    /// Get 'n' bits from end of the data.
-    fn _peek_n_bits(&self, size: usize) -> Result<&BitSlice<u8>, Error> {
-        let len = self.data.len();
-        if size > len {
-            return Err(Error::NLargerThanLength(size, self.data.len()));
-        }
-        self.data.get(len - size..).ok_or(Error::NoByte)
-    }
-
-    /// Construst a byte from the last 3 bits of the data.
-    pub fn peek_token(&self, token: Delimiter) -> Result<bool, Error> {
-        let bits = match token {
-            Delimiter::String => self._peek_n_bits(8)?,
-            Delimiter::Byte => self._peek_n_bits(8)?,
-            Delimiter::Map => self._peek_n_bits(8)?,
-            _ => self._peek_n_bits(3)?,
-        };
-        let mut byte = 0u8;
-        for (i, bit) in bits.iter().enumerate() {
-            if *bit {
-                byte |= 1 << i;
-            }
+    to_bytes_with_config(value, Config::default())
+}
+
+/// The same as [`to_bytes`] but the encoding is tweaked by the given [`Config`]. The
+/// bytes must be deserialized with the same config.
+pub fn to_bytes_with_config<T: Serialize>(value: &T, config: Config) -> Result<Vec<u8>, Error> {
+    let mut serializer = Serializer::with_sink(Vec::new(), config);
+    serializer.serialize_message(value)?;
+    let mut bytes = serializer.data.finish()?;
+    envelope::seal(config, &mut bytes, 0);
+    Ok(bytes)
+}
+
+/// Serialize the `value` into the given buffer and get the number of bytes written. Nothing
+/// is allocated (unless the config uses compression, canonical maps or interned field names),
+/// i.e. it never touches the heap on `no_std` targets; if the value doesn't fit,
+/// [`Error::BufferTooSmall`] is returned and the content of the buffer is unspecified.
+pub fn to_slice<T: Serialize>(value: &T, buffer: &mut [u8]) -> Result<usize, Error> {
+    to_slice_with_config(value, buffer, Config::default())
+}
+
+/// The same as [`to_slice`] but the encoding is tweaked by the given [`Config`].
+pub fn to_slice_with_config<T: Serialize>(
+    value: &T,
+    buffer: &mut [u8],
+    config: Config,
+) -> Result<usize, Error> {
+    if config.compression != Compression::None {
+        let bytes = to_bytes_with_config(value, config)?;
+        buffer
+            .get_mut(..bytes.len())
+            .ok_or(Error::BufferTooSmall)?
+            .copy_from_slice(&bytes);
+        return Ok(bytes.len());
+    }
+    let mut serializer = Serializer::with_sink(SliceSink::new(buffer), config);
+    serializer.serialize_message(value)?;
+    let mut sink = serializer.data.finish()?;
+    let (trailer, len) = envelope::trailer(config, sink.written());
+    sink.write(&trailer[..len])?;
+    Ok(sink.written().len())
+}
+
+/// Serialize the `value` and append the bytes to the given vector. If the serialization
+/// fails, the vector is left as it was.
+pub fn to_vec_in<T: Serialize>(value: &T, bytes: &mut Vec<u8>) -> Result<(), Error> {
+    to_vec_in_with_config(value, bytes, Config::default())
+}
+
+/// The same as [`to_vec_in`] but the encoding is tweaked by the given [`Config`].
+pub fn to_vec_in_with_config<T: Serialize>(
+    value: &T,
+    bytes: &mut Vec<u8>,
+    config: Config,
+) -> Result<(), Error> {
+    let len = bytes.len();
+    let mut serializer = Serializer::with_sink(&mut *bytes, config);
+    let result = serializer
+        .serialize_message(value)
+        .and_then(|_| serializer.data.finish().map(|_| ()));
+    if result.is_err() {
+        bytes.truncate(len);
+        return result;
+    }
+    envelope::seal(config, bytes, len);
+    Ok(())
+}
+
+/// Serialize the `value` into an async writer and flush it. Only the message is written, i.e.
+/// the reader has to read to the end of the stream; use
+/// [`RustFrCodec`](crate::codec::RustFrCodec) to send several messages over one stream.
+/// Requires the `async` feature.
+#[cfg(feature = "async")]
+pub async fn to_async_writer<W, T>(value: &T, writer: W) -> Result<(), Error>
+where
+    W: tokio::io::AsyncWrite + Unpin,
+    T: Serialize,
+{
+    to_async_writer_with_config(value, writer, Config::default()).await
+}
+
+/// The same as [`to_async_writer`] but the encoding is tweaked by the given [`Config`].
+#[cfg(feature = "async")]
+pub async fn to_async_writer_with_config<W, T>(
+    value: &T,
+    mut writer: W,
+    config: Config,
+) -> Result<(), Error>
+where
+    W: tokio::io::AsyncWrite + Unpin,
+    T: Serialize,
+{
+    use tokio::io::AsyncWriteExt;
+
+    let bytes = to_bytes_with_config(value, config)?;
+    writer.write_all(&bytes).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Serialize the `value` into a `futures::io::AsyncWrite` writer and flush it. Only the
+/// message is written, i.e. the reader has to read to the end of the stream. Requires the
+/// `futures-io` feature.
+#[cfg(feature = "futures-io")]
+pub async fn to_futures_writer<W, T>(value: &T, writer: W) -> Result<(), Error>
+where
+    W: futures_util::io::AsyncWrite + Unpin,
+    T: Serialize,
+{
+    to_futures_writer_with_config(value, writer, Config::default()).await
+}
+
+/// The same as [`to_futures_writer`] but the encoding is tweaked by the given [`Config`].
+#[cfg(feature = "futures-io")]
+pub async fn to_futures_writer_with_config<W, T>(
+    value: &T,
+    mut writer: W,
+    config: Config,
+) -> Result<(), Error>
+where
+    W: futures_util::io::AsyncWrite + Unpin,
+    T: Serialize,
+{
+    use futures_util::io::AsyncWriteExt;
+
+    let bytes = to_bytes_with_config(value, config)?;
+    writer.write_all(&bytes).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Get the number of bytes [`to_bytes`] would produce for the `value` without allocating
+/// the output. The value is serialized against a sink that only counts the bytes. With
+/// compression, the size is only known after compressing so the output is allocated.
+pub fn serialized_size<T: Serialize>(value: &T) -> Result<u64, Error> {
+    serialized_size_with_config(value, Config::default())
+}
+
+/// The same as [`serialized_size`] but for the output of [`to_bytes_with_config`].
+pub fn serialized_size_with_config<T: Serialize>(value: &T, config: Config) -> Result<u64, Error> {
+    Ok(serialized_bit_size_with_config(value, config)?.div_ceil(8))
+}
+
+/// Get the number of bits [`to_bytes`] would produce for the `value`, i.e. the length
+/// before the last byte is zero padded. With a checksum, the payload is padded before the
+/// checksum is appended so only the checksum is left unpadded.
+pub fn serialized_bit_size<T: Serialize>(value: &T) -> Result<u64, Error> {
+    serialized_bit_size_with_config(value, Config::default())
+}
+
+/// The same as [`serialized_bit_size`] but for the output of [`to_bytes_with_config`].
+pub fn serialized_bit_size_with_config<T: Serialize>(
+    value: &T,
+    config: Config,
+) -> Result<u64, Error> {
+    if config.compression != Compression::None {
+        return Ok(to_bytes_with_config(value, config)?.len() as u64 * 8);
+    }
+    let mut serializer = Serializer::with_sink(Discard, config);
+    serializer.serialize_message(value)?;
+    let bits = serializer.data.len() as u64;
+    match config.checksum.size() {
+        0 => Ok(bits),
+        len => Ok(bits.div_ceil(8) * 8 + len as u64 * 8),
+    }
+}
+
+/// Hash the canonical encoding of the `value` (see [`Config::canonical`]) with a new `H`, e.g.
+/// for cache keys or change detection. The encoding is fed straight to the hasher; nothing is
+/// allocated except for the entries of maps, which are sorted first.
+pub fn hash<H, T>(value: &T) -> Result<u64, Error>
+where
+    H: Hasher + Default,
+    T: Serialize,
+{
+    let mut hasher = H::default();
+    hash_into(value, &mut hasher)?;
+    Ok(hasher.finish())
+}
+
+/// The same as [`hash`] but the encoding is fed to the given hasher.
+pub fn hash_into<H, T>(value: &T, hasher: &mut H) -> Result<(), Error>
+where
+    H: Hasher,
+    T: Serialize,
+{
+    hash_into_with_config(value, hasher, Config::new().canonical(true))
+}
+
+/// The same as [`hash_into`] but the encoding is the one of [`to_bytes_with_config`] with the
+/// given [`Config`]. The bytes are fed with `Hasher::write` in chunks; for streaming hashers
+/// (e.g. SipHash or xxHash) the result is the same as hashing the output of
+/// [`to_bytes_with_config`] at once. Checksums and compression need the whole output, so
+/// the output is materialized with them.
+pub fn hash_into_with_config<H, T>(value: &T, hasher: &mut H, config: Config) -> Result<(), Error>
+where
+    H: Hasher,
+    T: Serialize,
+{
+    if config.checksum.size() > 0 || config.compression != Compression::None {
+        hasher.write(&to_bytes_with_config(value, config)?);
+        return Ok(());
+    }
+    let mut serializer = Serializer::with_sink(HashSink(hasher), config);
+    serializer.serialize_message(value)?;
+    serializer.data.finish()?;
+    Ok(())
+}
+
+impl Serializer {
+    /// Create a serializer with the default config.
+    pub fn new() -> Self {
+        Self::with_config(Config::default())
+    }
+
+    /// Create a serializer with the given config.
+    pub fn with_config(config: Config) -> Self {
+        Self::with_sink(Vec::new(), config)
+    }
+
+    /// Serialize the `value` and get the serialized bytes. The bytes of the previous value
+    /// are overwritten, i.e. the serializer is [reset](Self::reset) first.
+    pub fn serialize<T>(&mut self, value: &T) -> Result<&[u8], Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.reset();
+        self.serialize_message(value)?;
+        self.data.flush()?;
+        let bytes = self.data.sink_mut();
+        envelope::seal(self.config, bytes, 0);
+        Ok(bytes)
+    }
+
+    /// Throw away the serialized bytes but keep the capacity of the buffer.
+    pub fn reset(&mut self) {
+        self.data.clear();
+        self.field_names.clear();
+        self.raw = Raw::None;
+    }
+
+    /// Finish the serialization and get the serialized bytes. The last byte is zero padded.
+    pub fn into_inner(self) -> Result<Vec<u8>, Error> {
+        self.data.finish()
+    }
+}
+
+impl Default for Serializer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: Sink> Serializer<S> {
+    fn with_sink(sink: S, config: Config) -> Self {
+        Self {
+            data: BitWriter::new(sink, config.bit_order),
+            config,
+            field_names: BTreeMap::new(),
+            raw: Raw::None,
         }
-        Ok(byte == token as u8)
     }
 
-    /// Get token before 'n' bits.
-    pub fn peek_token_before_n_bits(&self, n: usize) -> Result<u8, Error> {
-        let bits = self._peek_n_bits(n + 3)?[0..3].as_ref();
-        let mut byte = 0u8;
-        for (i, bit) in bits.iter().enumerate() {
-            if *bit {
-                byte |= 1 << i;
-            }
+    /// Serialize the `value` as a whole message, i.e. preceded by the envelope header if
+    /// the config asks for one.
+    fn serialize_message<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        if let Some(header) = envelope::header(self.config) {
+            self.data.write_bytes(&header)?;
         }
-        Ok(byte)
+        value.serialize(self)
     }
 
-    /// Serialize a token to the data.
-    pub fn serialize_token(&mut self, token: Delimiter) {
+    /// Get the number of bits a token takes. If the config is byte-aligned, every token is a whole byte.
+    fn token_bits(&self, token: Delimiter) -> usize {
         match token {
-            Delimiter::String => {
-                self.data
-                    .extend(&[false, true, true, false, false, false, false, true]);
-                // 10000110
-            }
-            Delimiter::Byte => {
-                self.data
-                    .extend(&[true, true, true, false, false, false, false, true]);
-                // 10000111
-            }
-            Delimiter::Unit => {
-                self.data.extend(&[false, true, false]); // 010
+            _ if self.config.byte_aligned => 8,
+            Delimiter::String | Delimiter::Byte | Delimiter::Map => 8,
+            _ => 3,
+        }
+    }
+
+    /// Serialize a token to the data.
+    fn serialize_token(&mut self, token: Delimiter) -> Result<(), Error> {
+        self.data.write_bits(token as u64, self.token_bits(token))
+    }
+
+    /// Serialize an interned field name: 1 + varint(index) if the field name was already
+    /// written in this message, 0 + str() otherwise.
+    fn serialize_field_name(&mut self, name: &'static str) -> Result<(), Error> {
+        if let Some(&index) = self.field_names.get(name) {
+            self.serialize_bit(true)?;
+            return self.serialize_varint(index as u64);
+        }
+        self.field_names.insert(name, self.field_names.len());
+        self.serialize_bit(false)?;
+        self.data.write_bytes(name.as_bytes())?;
+        self.serialize_token(Delimiter::String)
+    }
+
+    /// Serialize a single bit to the data. If the config is byte-aligned, the bit takes a whole byte.
+    fn serialize_bit(&mut self, bit: bool) -> Result<(), Error> {
+        if self.config.byte_aligned {
+            self.data.write_bits(u64::from(bit), 8)
+        } else {
+            self.data.write_bit(bit)
+        }
+    }
+
+    /// Serialize the value on its own with the same config, e.g. to sort it before writing it.
+    fn encode<T>(&self, value: &T) -> Result<Encoded, Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        let mut serializer = Serializer::with_sink(Vec::new(), self.config);
+        value.serialize(&mut serializer)?;
+        let bits = serializer.data.len();
+        Ok((serializer.data.finish()?, bits))
+    }
+
+    /// Serialize the kind tag of a sequence in packed mode. If the config is byte-aligned, the
+    /// kind tag is a whole byte.
+    fn serialize_kind(&mut self, kind: Kind) -> Result<(), Error> {
+        let bits = match self.config.byte_aligned {
+            true => 8,
+            false => Kind::BITS,
+        };
+        self.data.write_bits(kind as u64, bits)
+    }
+
+    /// Serialize the Little Endian bytes of a fixed-width number in the byte order of the config.
+    fn serialize_number<const N: usize>(&mut self, bytes: [u8; N]) -> Result<(), Error> {
+        self.data.write_bytes(&self.config.endian.order(bytes))
+    }
+
+    /// Serialize a float in compact mode: its tag (a whole byte if the config is
+    /// byte-aligned) followed by the representation.
+    fn serialize_compact_float(&mut self, compact: Compact) -> Result<(), Error> {
+        let bits = match self.config.byte_aligned {
+            true => 8,
+            false => float::TAG_BITS,
+        };
+        self.data.write_bits(compact.tag(), bits)?;
+        match compact {
+            Compact::Int(zigzag) => self.serialize_varint(zigzag),
+            Compact::F16(bits) => self.serialize_number(bits.to_le_bytes()),
+            Compact::F32(v) => self.serialize_number(v.to_le_bytes()),
+            Compact::F64(v) => self.serialize_number(v.to_le_bytes()),
+        }
+    }
+
+    /// Serialize a variable length unsigned integer (LEB128) to the data. Each byte
+    /// holds 7 bits of the value and the highest bit is set if more bytes follow.
+    fn serialize_varint(&mut self, mut value: u64) -> Result<(), Error> {
+        let mut bytes = [0u8; 10];
+        let mut len = 0;
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                bytes[len] = byte;
+                len += 1;
+                break;
             }
-            Delimiter::Seq => {
-                self.data.extend(&[true, true, false]); // 011
+            bytes[len] = byte | 0x80;
+            len += 1;
+        }
+        self.data.write_bytes(&bytes[..len])
+    }
+
+    /// Serialize the length header of a sequence or a map. Only used when the config
+    /// is length-prefixed: 1 -> known length followed by the length as a varint,
+    /// 0 -> unknown length (delimited).
+    /// Returns the length if it was written.
+    fn serialize_length(&mut self, len: Option<usize>) -> Result<Option<usize>, Error> {
+        if !self.config.length_prefixed {
+            return Ok(None);
+        }
+        self.serialize_bit(len.is_some())?;
+        if let Some(len) = len {
+            self.serialize_varint(len as u64)?;
+        }
+        Ok(len)
+    }
+}
+
+/// Handles the serialization of sequences and maps. It keeps track of the number of
+/// elements serialized so far so that delimiters are only written between elements and so
+/// that the length written up front can be verified.
+#[derive(Debug)]
+pub struct Compound<'a, S: Sink> {
+    serializer: &'a mut Serializer<S>,
+    len: Option<usize>,
+    count: usize,
+    packing: Packing,
+    // the elements of a packed run, written when the sequence ends.
+    run: Vec<Primitive>,
+    // the entries of a map in canonical mode, written sorted when the map ends.
+    entries: Option<Vec<(Encoded, Encoded)>>,
+}
+
+/// A value serialized on its own: the bytes and the number of bits used.
+type Encoded = (Vec<u8>, usize);
+
+/// The state of a sequence in packed mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Packing {
+    /// Not packed: a map, a tuple or a sequence whose elements can't be packed.
+    Off,
+    /// The kind tag is not written yet; holds the length of the sequence, if known.
+    Pending(Option<usize>),
+    /// A packed run of elements of the kind, buffered until the sequence ends.
+    Run(Kind),
+}
+
+impl<'a, S: Sink> Compound<'a, S> {
+    fn new(serializer: &'a mut Serializer<S>, len: Option<usize>) -> Self {
+        Self {
+            serializer,
+            len,
+            count: 0,
+            packing: Packing::Off,
+            run: Vec::new(),
+            entries: None,
+        }
+    }
+
+    /// Serialize an element of a sequence. SEQ_VALUE_DELIMITER is only written between
+    /// elements (and before the first one if the config is unambiguous) and only if the length
+    /// of the sequence is not known.
+    ///
+    /// In packed mode, a sequence of known length whose first element is a fixed-width
+    /// primitive starts out as a packed run. The elements of the run are buffered until the
+    /// sequence ends; if an element of another kind comes along, the sequence is written
+    /// unpacked instead.
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        let primitive = match self.packing {
+            Packing::Off => None,
+            _ => Primitive::of(value),
+        };
+        if let Packing::Pending(len) = self.packing {
+            match (len, primitive) {
+                (Some(len), Some(primitive)) => {
+                    self.packing = Packing::Run(primitive.kind());
+                    self.run.reserve(len);
+                }
+                _ => {
+                    self.packing = Packing::Off;
+                    self.serializer.serialize_kind(Kind::Other)?;
+                }
             }
-            Delimiter::SeqValue => {
-                self.data.extend(&[false, false, true]); // 100
+        }
+        if let Packing::Run(kind) = self.packing {
+            match primitive.filter(|primitive| primitive.kind() == kind) {
+                Some(primitive) => {
+                    self.run.push(primitive);
+                    self.count += 1;
+                    return Ok(());
+                }
+                None => self.unpack()?,
             }
-            Delimiter::Map => {
-                self.data
-                    .extend(&[true, true, false, true, false, false, false, true]);
-                // 10001011
+        }
+        self.serialize_seq_value(self.count)?;
+        self.count += 1;
+        value.serialize(&mut *self.serializer)
+    }
+
+    /// Give up on the packed run: write the kind tag of an unpacked sequence followed by the
+    /// elements buffered so far.
+    fn unpack(&mut self) -> Result<(), Error> {
+        self.packing = Packing::Off;
+        self.serializer.serialize_kind(Kind::Other)?;
+        for (index, primitive) in core::mem::take(&mut self.run).into_iter().enumerate() {
+            self.serialize_seq_value(index)?;
+            primitive.serialize(&mut *self.serializer)?;
+        }
+        Ok(())
+    }
+
+    /// Serialize the SEQ_VALUE_DELIMITER in front of the element at `index` if the length of
+    /// the sequence is not known.
+    fn serialize_seq_value(&mut self, index: usize) -> Result<(), Error> {
+        if self.len.is_some() || (index == 0 && !self.serializer.config.unambiguous) {
+            return Ok(());
+        }
+        self.serializer.serialize_token(Delimiter::SeqValue)
+    }
+
+    /// Write the packed run: the kind tag, the length (unless it was written up front
+    /// already) and the elements. Runs of bools are padded to a byte boundary if the config is
+    /// byte-aligned.
+    fn write_run(&mut self, kind: Kind) -> Result<(), Error> {
+        let serializer = &mut *self.serializer;
+        serializer.serialize_kind(kind)?;
+        if self.len.is_none() {
+            serializer.serialize_varint(self.run.len() as u64)?;
+            self.len = Some(self.run.len());
+        }
+        for &primitive in &self.run {
+            let primitive = match primitive {
+                Primitive::F32(v) => Primitive::F32(float::canonical_f32(v, serializer.config)?),
+                Primitive::F64(v) => Primitive::F64(float::canonical_f64(v, serializer.config)?),
+                primitive => primitive,
+            };
+            primitive.write(&mut serializer.data, serializer.config.endian)?;
+        }
+        if kind == Kind::Bool && serializer.config.byte_aligned {
+            serializer.data.flush()?;
+        }
+        Ok(())
+    }
+
+    /// End a sequence. SEQ_DELIMITER is only written if the length of the sequence is not known.
+    /// In packed mode, empty sequences get their kind tag here and packed runs are written.
+    fn end_seq(mut self) -> Result<(), Error> {
+        match self.packing {
+            Packing::Pending(_) => self.serializer.serialize_kind(Kind::Other)?,
+            Packing::Run(kind) => self.write_run(kind)?,
+            Packing::Off => {}
+        }
+        match self.len {
+            Some(len) if len != self.count => Err(Error::LengthMismatch(len, self.count)),
+            Some(_) => Ok(()),
+            None => self.serializer.serialize_token(Delimiter::Seq),
+        }
+    }
+
+    /// Serialize a key of a map. MAP_KEY_DELIMITER is only written if the length of the map is not known.
+    /// In canonical mode, the key is only buffered.
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        if let Some(entries) = &mut self.entries {
+            let key = self.serializer.encode(key)?;
+            entries.push((key, (Vec::new(), 0)));
+            return Ok(());
+        }
+        self.count += 1;
+        key.serialize(&mut *self.serializer)?;
+        if self.len.is_none() {
+            self.serializer.serialize_token(Delimiter::MapKey)?;
+        }
+        Ok(())
+    }
+
+    /// Serialize a field of a struct, i.e. a key-value pair whose key is the field name. The
+    /// field name is interned if the config says so.
+    fn serialize_struct_field<T>(&mut self, name: &'static str, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        if !self.serializer.config.intern_field_names {
+            self.serialize_key(name)?;
+            return self.serialize_value(value);
+        }
+        self.count += 1;
+        self.serializer.serialize_field_name(name)?;
+        if self.len.is_none() {
+            self.serializer.serialize_token(Delimiter::MapKey)?;
+        }
+        self.serialize_value(value)
+    }
+
+    /// Serialize a value of a map. MAP_VALUE_DELIMITER is only written if the length of the map is not known.
+    /// In canonical mode, the value is only buffered.
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        if let Some(entries) = &mut self.entries {
+            let value = self.serializer.encode(value)?;
+            return match entries.last_mut() {
+                Some(entry) => {
+                    entry.1 = value;
+                    Ok(())
+                }
+                None => Err(Error::SerializationError(
+                    "serialize_value called before serialize_key".to_string(),
+                )),
+            };
+        }
+        value.serialize(&mut *self.serializer)?;
+        if self.len.is_none() {
+            self.serializer.serialize_token(Delimiter::MapValue)?;
+        }
+        Ok(())
+    }
+
+    /// End a map. MAP_DELIMITER is only written if the length of the map is not known.
+    fn end_map(mut self) -> Result<(), Error> {
+        if let Some(entries) = self.entries.take() {
+            return self.end_canonical_map(entries);
+        }
+        match self.len {
+            Some(len) if len != self.count => Err(Error::LengthMismatch(len, self.count)),
+            Some(_) => Ok(()),
+            None => self.serializer.serialize_token(Delimiter::Map),
+        }
+    }
+}
+
+impl<S: Sink> Compound<'_, S> {
+    /// End a map in canonical mode: sort the buffered entries by the encoding of their keys,
+    /// reject duplicate keys and write the map, now of known length.
+    fn end_canonical_map(self, mut entries: Vec<(Encoded, Encoded)>) -> Result<(), Error> {
+        if let Some(len) = self.len {
+            if len != entries.len() {
+                return Err(Error::LengthMismatch(len, entries.len()));
             }
-            Delimiter::MapKey => {
-                self.data.extend(&[false, true, true]); // 110
+        }
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        if entries.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+            return Err(Error::DuplicateKey);
+        }
+        let serializer = self.serializer;
+        let len = serializer.serialize_length(Some(entries.len()))?;
+        for ((key, key_bits), (value, value_bits)) in &entries {
+            serializer.data.write_bit_slice(key, *key_bits)?;
+            if len.is_none() {
+                serializer.serialize_token(Delimiter::MapKey)?;
             }
-            Delimiter::MapValue => {
-                self.data.extend(&[true, true, true]); // 111
+            serializer.data.write_bit_slice(value, *value_bits)?;
+            if len.is_none() {
+                serializer.serialize_token(Delimiter::MapValue)?;
             }
         }
+        match len {
+            Some(_) => Ok(()),
+            None => serializer.serialize_token(Delimiter::Map),
+        }
     }
 }
 
-impl<'a> Serializer for &'a mut CustomSerializer {
+impl<'a, S: Sink> ser::Serializer for &'a mut Serializer<S> {
     type Ok = ();
     type Error = Error;
 
-    type SerializeSeq = Self;
-    type SerializeMap = Self;
+    type SerializeSeq = Compound<'a, S>;
+    type SerializeMap = Compound<'a, S>;
 
-    type SerializeTuple = Self;
-    type SerializeStruct = Self;
+    type SerializeTuple = Compound<'a, S>;
+    type SerializeStruct = Compound<'a, S>;
 
-    type SerializeTupleStruct = Self;
-    type SerializeTupleVariant = Self;
-    type SerializeStructVariant = Self;
+    type SerializeTupleStruct = Compound<'a, S>;
+    type SerializeTupleVariant = Compound<'a, S>;
+    type SerializeStructVariant = Compound<'a, S>;
 
-    /// bool: 0 -> false, 1 -> true (1 bit)
+    /// bool: 0 -> false, 1 -> true (1 bit; 1 byte if byte-aligned)
     fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
-        self.data.push(v);
-        Ok(())
+        self.serialize_bit(v)
     }
 
-    /// i8, i16, i32, i64: Little Endian (1, 2, 4, 8 bytes)
+    /// i8, i16, i32, i64: Little Endian or Big Endian (1, 2, 4, 8 bytes)
     fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
-        self.data.extend(&v.to_le_bytes());
-        Ok(())
+        self.serialize_number(v.to_le_bytes())
     }
     fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
-        self.data.extend(&v.to_le_bytes());
-        Ok(())
+        self.serialize_number(v.to_le_bytes())
     }
     fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
-        self.data.extend(&v.to_le_bytes());
-        Ok(())
+        self.serialize_number(v.to_le_bytes())
     }
     fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
-        self.data.extend(&v.to_le_bytes());
-        Ok(())
+        self.serialize_number(v.to_le_bytes())
     }
 
-    /// u8, u16, u32, u64: Little Endian (1, 2, 4, 8 bytes)
+    /// u8, u16, u32, u64: Little Endian or Big Endian (1, 2, 4, 8 bytes)
     fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
-        self.data.extend(&v.to_le_bytes());
-        Ok(())
+        self.serialize_number(v.to_le_bytes())
     }
     fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
-        self.data.extend(&v.to_le_bytes());
-        Ok(())
+        self.serialize_number(v.to_le_bytes())
     }
     fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
-        self.data.extend(&v.to_le_bytes());
-        Ok(())
+        self.serialize_number(v.to_le_bytes())
     }
     fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
-        self.data.extend(&v.to_le_bytes());
-        Ok(())
+        self.serialize_number(v.to_le_bytes())
     }
 
-    /// f32, f64: Little Endian (4, 8 bytes)
+    /// f32, f64: Little Endian or Big Endian (4, 8 bytes)
+    /// compact floats: tag + int (zigzag varint) | f16 | f32 | f64
+    /// f16 wrapper: 2 bytes
     fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
-        self.data.extend(&v.to_le_bytes());
-        Ok(())
+        // take the marker first so that a rejected NaN doesn't leave it set.
+        #[cfg(feature = "f16")]
+        let raw = core::mem::replace(&mut self.raw, Raw::None);
+        let v = float::canonical_f32(v, self.config)?;
+        #[cfg(feature = "f16")]
+        if raw == Raw::F16 {
+            return self.serialize_number(float::to_f16_bits(v).to_le_bytes());
+        }
+        if self.config.compact_floats {
+            return self.serialize_compact_float(Compact::of(f64::from(v), false));
+        }
+        self.serialize_number(v.to_le_bytes())
     }
     fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
-        self.data.extend(&v.to_le_bytes());
-        Ok(())
+        let v = float::canonical_f64(v, self.config)?;
+        if self.config.compact_floats {
+            return self.serialize_compact_float(Compact::of(v, true));
+        }
+        self.serialize_number(v.to_le_bytes())
     }
 
     /// char: as u32 (4 bytes)
@@ -221,33 +832,42 @@ impl<'a> Serializer for &'a mut CustomSerializer {
     }
     /// str: bytes STRING_DELIMITER
     fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
-        self.data.extend(v.as_bytes());
-        self.serialize_token(Delimiter::String);
-        Ok(())
+        self.data.write_bytes(v.as_bytes())?;
+        self.serialize_token(Delimiter::String)
     }
     /// bytes: bytes BYTE_DELIMITER
+    /// delta blob (see [`delta`]): bytes
     fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
-        self.data.extend(v);
-        self.serialize_token(Delimiter::Byte);
-        Ok(())
+        if core::mem::replace(&mut self.raw, Raw::None) == Raw::Delta {
+            return self.data.write_bytes(v);
+        }
+        self.data.write_bytes(v)?;
+        self.serialize_token(Delimiter::Byte)
     }
 
     /// unit: UNIT (null)
     fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
-        self.serialize_token(Delimiter::Unit);
-        Ok(())
+        self.serialize_token(Delimiter::Unit)
     }
 
     /// option:
     /// None -> unit()
+    /// unambiguous options: None -> bool(false)
     fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        if self.config.unambiguous {
+            return self.serialize_bool(false);
+        }
         self.serialize_unit()
     }
     /// Some -> self
-    fn serialize_some<T: ?Sized>(self, value: &T) -> Result<Self::Ok, Self::Error>
+    /// unambiguous options: Some -> bool(true) + self
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok, Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
+        if self.config.unambiguous {
+            self.serialize_bool(true)?;
+        }
         value.serialize(self)
     }
 
@@ -257,14 +877,20 @@ impl<'a> Serializer for &'a mut CustomSerializer {
         self.serialize_unit()
     }
     /// newtype_struct: self
-    fn serialize_newtype_struct<T: ?Sized>(
+    fn serialize_newtype_struct<T>(
         self,
-        _name: &'static str,
+        name: &'static str,
         value: &T,
     ) -> Result<Self::Ok, Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
+        self.raw = match name {
+            delta::TOKEN => Raw::Delta,
+            #[cfg(feature = "f16")]
+            float::F16_TOKEN => Raw::F16,
+            _ => Raw::None,
+        };
         value.serialize(self)
     }
     /// tuple_struct: tuple()
@@ -287,7 +913,7 @@ impl<'a> Serializer for &'a mut CustomSerializer {
         self.serialize_u32(variant_index)
     }
     /// newtype_variant: variant_index self
-    fn serialize_newtype_variant<T: ?Sized>(
+    fn serialize_newtype_variant<T>(
         self,
         _name: &'static str,
         variant_index: u32,
@@ -295,7 +921,7 @@ impl<'a> Serializer for &'a mut CustomSerializer {
         value: &T,
     ) -> Result<Self::Ok, Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         self.serialize_u32(variant_index)?;
         value.serialize(self)
@@ -309,7 +935,7 @@ impl<'a> Serializer for &'a mut CustomSerializer {
         len: usize,
     ) -> Result<Self::SerializeTupleVariant, Self::Error> {
         self.serialize_u32(variant_index)?;
-        self.serialize_seq(Some(len))
+        self.serialize_tuple(len)
     }
     /// struct_variant: variant_index struct()
     fn serialize_struct_variant(
@@ -320,22 +946,49 @@ impl<'a> Serializer for &'a mut CustomSerializer {
         len: usize,
     ) -> Result<Self::SerializeStructVariant, Self::Error> {
         self.serialize_u32(variant_index)?;
-        self.serialize_map(Some(len))
+        self.serialize_struct("", len)
     }
 
     /// sequences: SEQ_DELIMITER + value_1 + SEQ_VALUE_DELIMITER + value_2 + SEQ_VALUE_DELIMITER + ... SEQ_DELIMITER
-    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
-        self.serialize_token(Delimiter::Seq);
-        Ok(self)
+    /// length-prefixed sequences (known length): SEQ_DELIMITER + 1 + varint(len) + value_1 + value_2 + ...
+    /// length-prefixed sequences (unknown length): SEQ_DELIMITER + 0 + seq()
+    /// unambiguous sequences: SEQ_DELIMITER + SEQ_VALUE_DELIMITER + value_1 + SEQ_VALUE_DELIMITER + value_2 + ... SEQ_DELIMITER
+    /// packed sequences: SEQ_DELIMITER + [length header] + kind + [varint(len)] + value_1 + value_2 + ...
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        self.serialize_token(Delimiter::Seq)?;
+        let packing = match self.config.packed_sequences {
+            true => Packing::Pending(len),
+            false => Packing::Off,
+        };
+        let written = self.serialize_length(len)?;
+        let mut compound = Compound::new(self, written);
+        compound.packing = packing;
+        Ok(compound)
     }
     /// maps: key_1 + MAP_KEY_DELIMITER + value_1 + MAP_VALUE_DELIMITER + key_2 + MAP_KEY_DELIMITER + value_2 + MAP_VALUE_DELIMITER +... MAP_DELIMITER
-    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
-        Ok(self)
+    /// length-prefixed maps (known length): 1 + varint(len) + key_1 + value_1 + key_2 + value_2 + ...
+    /// length-prefixed maps (unknown length): 0 + map()
+    /// canonical maps: map() with the entries sorted by the encoding of their keys, always of known length
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        if self.config.canonical {
+            if self.config.intern_field_names {
+                return Err(Error::IncompatibleConfig(
+                    "canonical maps can't intern field names",
+                ));
+            }
+            let mut compound = Compound::new(self, len);
+            compound.entries = Some(Vec::with_capacity(len.unwrap_or(0)));
+            return Ok(compound);
+        }
+        let len = self.serialize_length(len)?;
+        Ok(Compound::new(self, len))
     }
 
-    /// tuples: seq()
+    /// tuples: seq(), never packed
     fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Self::Error> {
-        self.serialize_seq(Some(len))
+        self.serialize_token(Delimiter::Seq)?;
+        let len = self.serialize_length(Some(len))?;
+        Ok(Compound::new(self, len))
     }
     /// structs: map()
     fn serialize_struct(
@@ -343,185 +996,147 @@ impl<'a> Serializer for &'a mut CustomSerializer {
         _name: &'static str,
         len: usize,
     ) -> Result<Self::SerializeStruct, Self::Error> {
-        self.serialize_map(Some(len))
+        let len = self.serialize_length(Some(len))?;
+        Ok(Compound::new(self, len))
     }
 }
 
-impl<'a> SerializeSeq for &'a mut CustomSerializer {
+impl<S: Sink> SerializeSeq for Compound<'_, S> {
     type Ok = ();
     type Error = Error;
 
     /// Serialize an element of the sequence.
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        if !self.peek_token(Delimiter::Seq)? {
-            self.serialize_token(Delimiter::SeqValue);
-        }
-        value.serialize(&mut **self)
+        Compound::serialize_element(self, value)
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        self.serialize_token(Delimiter::Seq);
-        Ok(())
+        self.end_seq()
     }
 }
-impl<'a> SerializeMap for &'a mut CustomSerializer {
+impl<S: Sink> SerializeMap for Compound<'_, S> {
     type Ok = ();
     type Error = Error;
 
     /// Serialize a key of a given element of the map.
-    fn serialize_key<T: ?Sized>(&mut self, key: &T) -> Result<(), Self::Error>
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        key.serialize(&mut **self)?;
-        self.serialize_token(Delimiter::MapKey);
-        Ok(())
+        Compound::serialize_key(self, key)
     }
 
     /// Serialize a value of a given element of the map.
-    fn serialize_value<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        value.serialize(&mut **self)?;
-        self.serialize_token(Delimiter::MapValue);
-        Ok(())
+        Compound::serialize_value(self, value)
     }
 
     /// End the map serialization.
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        self.serialize_token(Delimiter::Map);
-        Ok(())
+        self.end_map()
     }
 }
 
 // = seq()
-impl<'a> SerializeTuple for &'a mut CustomSerializer {
+impl<S: Sink> SerializeTuple for Compound<'_, S> {
     type Ok = ();
     type Error = Error;
 
     /// Serialize an element of the tuple.
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        if !self.peek_token(Delimiter::Seq)? {
-            self.serialize_token(Delimiter::SeqValue);
-        }
-        value.serialize(&mut **self)
+        Compound::serialize_element(self, value)
     }
 
     /// End the tuple serialization.
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        self.serialize_token(Delimiter::Seq);
-        Ok(())
+        self.end_seq()
     }
 }
 // = map()
-impl<'a> SerializeStruct for &'a mut CustomSerializer {
+impl<S: Sink> SerializeStruct for Compound<'_, S> {
     type Ok = ();
     type Error = Error;
 
     /// Serialize a field of the struct. Structs treated as a key-value pair i.e. a map.
     /// There is no difference between a struct and a map in the serialization format.
-    fn serialize_field<T: ?Sized>(
-        &mut self,
-        key: &'static str,
-        value: &T,
-    ) -> Result<(), Self::Error>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        key.serialize(&mut **self)?;
-        self.serialize_token(Delimiter::MapKey);
-        value.serialize(&mut **self)?;
-        self.serialize_token(Delimiter::MapValue);
-        Ok(())
+        self.serialize_struct_field(key, value)
     }
 
     /// End the struct serialization.
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        self.serialize_token(Delimiter::Map);
-        Ok(())
+        self.end_map()
     }
 }
 
 // = seq()
-impl<'a> SerializeTupleStruct for &'a mut CustomSerializer {
+impl<S: Sink> SerializeTupleStruct for Compound<'_, S> {
     type Ok = ();
     type Error = Error;
 
     /// Serialize an element of the tuple. Tuple structs treated as a sequence.
     /// There is no difference between a tuple struct and a sequence in the serialization format.
-    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        if !self.peek_token(Delimiter::Seq)? {
-            self.serialize_token(Delimiter::SeqValue);
-        }
-        value.serialize(&mut **self)
+        self.serialize_element(value)
     }
 
     /// End the tuple struct serialization.
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        self.serialize_token(Delimiter::Seq);
-        Ok(())
+        self.end_seq()
     }
 }
 
 // = tuple() = seq()
-impl<'a> SerializeTupleVariant for &'a mut CustomSerializer {
+impl<S: Sink> SerializeTupleVariant for Compound<'_, S> {
     type Ok = ();
     type Error = Error;
 
     /// Serialize an element of the tuple in an enum variant. Tuple variants treated as a sequence.
     /// There is no difference between a tuple variant and a sequence in the serialization format.
-    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        if self.peek_token_before_n_bits(32)? != Delimiter::Seq as u8 {
-            self.serialize_token(Delimiter::SeqValue);
-        }
-        value.serialize(&mut **self)
+        self.serialize_element(value)
     }
 
     /// End the tuple variant serialization.
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        self.serialize_token(Delimiter::Seq);
-        Ok(())
+        self.end_seq()
     }
 }
 
 // = struct() = map()
-impl<'a> SerializeStructVariant for &'a mut CustomSerializer {
+impl<S: Sink> SerializeStructVariant for Compound<'_, S> {
     type Ok = ();
     type Error = Error;
 
     /// Serialize a field of the struct in an enum variant. Struct variants treated as a key-value pair i.e. a map.
     /// There is no difference between a struct variant and a map in the serialization format.
-    fn serialize_field<T: ?Sized>(
-        &mut self,
-        key: &'static str,
-        value: &T,
-    ) -> Result<(), Self::Error>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        key.serialize(&mut **self)?;
-        self.serialize_token(Delimiter::MapKey);
-        value.serialize(&mut **self)?;
-        self.serialize_token(Delimiter::MapValue);
-        Ok(())
+        self.serialize_struct_field(key, value)
     }
 
     /// End the struct variant serialization.
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        self.serialize_token(Delimiter::Map);
-        Ok(())
+        self.end_map()
     }
 }